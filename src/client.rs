@@ -5,13 +5,20 @@ use serde::Deserialize;
 use tracing::{info, warn};
 
 use crate::config::Config;
-use crate::signer::AliyunSigner;
+use crate::rpc::{AliyunRpcClient, ApiError};
+use crate::{cms, governor, history, swas, templates};
+
+/// 串行化工单提交：从频率检查到写入历史记录之间不允许其他提交插入，
+/// 否则飞书、Telegram 同时审批时都能通过频率检查
+static SUBMIT_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
 
 /// 阿里云工单 API 客户端
+///
+/// 查询类接口用 GET；会修改工单的接口（CreateTicket、ReplyTicket、CloseTicket）统一用 POST 表单，
+/// 标题、描述等长文本放在 body 中，不受 URL 长度限制，也不会出现在访问日志里。
 pub struct WorkorderClient {
     config: Config,
-    signer: AliyunSigner,
-    http: reqwest::Client,
+    rpc: AliyunRpcClient,
 }
 
 // ---- API 响应结构 ----
@@ -26,6 +33,29 @@ struct ApiResponse<T> {
     request_id: Option<String>,
 }
 
+impl<T> ApiResponse<T> {
+    /// 校验 Success 字段，失败时带上错误码和 RequestId 方便排查
+    fn ensure_success(&self, action: &str) -> Result<()> {
+        if self.success != Some(true) {
+            return Err(ApiError {
+                action: action.to_string(),
+                status: 200,
+                code: self.code.map(|c| c.to_string()),
+                message: self.message.clone().unwrap_or_default(),
+                request_id: self.request_id.clone(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// 校验 Success 字段并取出 Data
+    fn into_data(self, action: &str) -> Result<T> {
+        self.ensure_success(action)?;
+        self.data.with_context(|| format!("{} 返回数据为空", action))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct ProductDirectory {
@@ -48,83 +78,71 @@ struct Category {
     category_name: Option<String>,
 }
 
-impl WorkorderClient {
-    pub fn new(config: Config) -> Self {
-        let signer =
-            AliyunSigner::new(config.access_key_id.clone(), config.access_key_secret.clone());
-        let http = reqwest::Client::new();
-        Self {
-            config,
-            signer,
-            http,
-        }
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TicketPage {
+    list: Option<Vec<TicketInfo>>,
+    total: Option<u64>,
+}
+
+/// 工单概要信息（ListTickets 返回的单条记录或 GetTicket 的返回数据）
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TicketInfo {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub ticket_status: Option<String>,
+    /// 创建时间（毫秒时间戳）
+    pub add_time: Option<i64>,
+    pub category_id: Option<u64>,
+}
+
+impl TicketInfo {
+    /// 工单状态，缺失时视为处理中
+    pub fn status(&self) -> &str {
+        self.ticket_status.as_deref().unwrap_or("dealing")
     }
 
-    /// 发送 API 请求
-    async fn call_api(
-        &self,
-        action: &str,
-        query_params: &mut BTreeMap<String, String>,
-    ) -> Result<String> {
-        let nonce = uuid::Uuid::new_v4().to_string();
-        let timestamp = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
-
-        let host = &self.config.endpoint;
-        let url = format!("https://{}/", host);
-
-        // 公共头
-        let mut headers = BTreeMap::new();
-        headers.insert("host".to_string(), host.clone());
-        headers.insert("x-acs-action".to_string(), action.to_string());
-        headers.insert("x-acs-version".to_string(), self.config.api_version.clone());
-        headers.insert("x-acs-date".to_string(), timestamp);
-        headers.insert("x-acs-signature-nonce".to_string(), nonce);
-
-        // 对于 RPC 风格的 GET 请求，body 为空
-        let body = "";
-        let content_sha256 = {
-            use sha2::{Digest, Sha256};
-            let mut hasher = Sha256::new();
-            hasher.update(body.as_bytes());
-            hex::encode(hasher.finalize())
-        };
-        headers.insert("x-acs-content-sha256".to_string(), content_sha256);
-
-        // 签名
-        let authorization = self
-            .signer
-            .sign("GET", query_params, &headers, body)
-            .context("签名计算失败")?;
-
-        // 构建 reqwest 请求
-        let query_vec: Vec<(String, String)> = query_params
-            .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect();
-
-        let resp = self
-            .http
-            .get(&url)
-            .query(&query_vec)
-            .header("Host", host.as_str())
-            .header("x-acs-action", action)
-            .header("x-acs-version", &self.config.api_version)
-            .header("x-acs-date", headers.get("x-acs-date").unwrap().as_str())
-            .header("x-acs-signature-nonce", headers.get("x-acs-signature-nonce").unwrap().as_str())
-            .header("x-acs-content-sha256", headers.get("x-acs-content-sha256").unwrap().as_str())
-            .header("Authorization", &authorization)
-            .send()
-            .await
-            .context("HTTP 请求失败")?;
+    /// 创建时间的本地时间字符串
+    pub fn created_at(&self) -> String {
+        self.add_time
+            .and_then(chrono::DateTime::from_timestamp_millis)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
 
-        let status = resp.status();
-        let text = resp.text().await.context("读取响应失败")?;
+/// 提交成功的工单
+#[derive(Debug, Clone)]
+pub struct SubmittedTicket {
+    pub ticket_id: String,
+    /// CreateTicket 请求的 RequestId，便于向阿里云追溯
+    pub request_id: Option<String>,
+}
 
-        if !status.is_success() {
-            anyhow::bail!("API 返回错误 (HTTP {}): {}", status, text);
-        }
+/// 工单状态的中文描述
+pub fn status_label(status: &str) -> &str {
+    match status {
+        "dealing" => "处理中",
+        "pending" => "待您反馈",
+        "completed" => "已处理",
+        "closed" => "已关闭",
+        "evaluated" => "已评价",
+        other => other,
+    }
+}
+
+/// 工单是否已被阿里云处理完毕或关闭（无需继续跟踪）
+pub fn is_finished(status: &str) -> bool {
+    matches!(status, "completed" | "closed" | "evaluated")
+}
 
-        Ok(text)
+impl WorkorderClient {
+    pub fn new(config: Config) -> Self {
+        // api.scheme 只作用于工单 API，STS、SWAS、云监控始终走 https
+        let rpc = AliyunRpcClient::new(&config, &config.endpoint, &config.api_version)
+            .with_scheme(&config.api.scheme);
+        Self { config, rpc }
     }
 
     /// 查询产品列表，找到轻量应用服务器的 ProductId
@@ -133,18 +151,9 @@ impl WorkorderClient {
         let mut params = BTreeMap::new();
         params.insert("Language".to_string(), "zh".to_string());
 
-        let resp_text = self.call_api("ListProducts", &mut params).await?;
         let resp: ApiResponse<Vec<ProductDirectory>> =
-            serde_json::from_str(&resp_text).context("解析 ListProducts 响应失败")?;
-
-        if resp.success != Some(true) {
-            anyhow::bail!(
-                "ListProducts 失败: {}",
-                resp.message.unwrap_or_default()
-            );
-        }
-
-        let directories = resp.data.context("ListProducts 返回数据为空")?;
+            self.rpc.get("ListProducts", &params).await?;
+        let directories = resp.into_data("ListProducts")?;
 
         // 搜索轻量应用服务器
         for dir in &directories {
@@ -165,11 +174,12 @@ impl WorkorderClient {
         warn!("未找到轻量应用服务器，列出所有产品:");
         for dir in &directories {
             let dir_name = dir.directory_name.as_deref().unwrap_or("未知");
+            let dir_id = dir.directory_id.unwrap_or(0);
             if let Some(products) = &dir.product_list {
                 for product in products {
                     let name = product.product_name.as_deref().unwrap_or("未知");
                     let pid = product.product_id.unwrap_or(0);
-                    warn!("  [{dir_name}] {name} (ProductId: {pid})");
+                    warn!("  [{dir_name} #{dir_id}] {name} (ProductId: {pid})");
                 }
             }
         }
@@ -184,18 +194,9 @@ impl WorkorderClient {
         params.insert("ProductId".to_string(), product_id.to_string());
         params.insert("Language".to_string(), "zh".to_string());
 
-        let resp_text = self.call_api("ListCategories", &mut params).await?;
         let resp: ApiResponse<Vec<Category>> =
-            serde_json::from_str(&resp_text).context("解析 ListCategories 响应失败")?;
-
-        if resp.success != Some(true) {
-            anyhow::bail!(
-                "ListCategories 失败: {}",
-                resp.message.unwrap_or_default()
-            );
-        }
-
-        let categories = resp.data.context("ListCategories 返回数据为空")?;
+            self.rpc.get("ListCategories", &params).await?;
+        let categories = resp.into_data("ListCategories")?;
 
         // 优先选择含有"网络"、"带宽"等关键词的分类
         let keywords = ["带宽", "网络", "限速", "bandwidth", "network"];
@@ -234,37 +235,149 @@ impl WorkorderClient {
     }
 
     /// 提交工单
-    pub async fn create_ticket(&self, category_id: u64) -> Result<String> {
+    pub async fn create_ticket(&self, category_id: u64, description: &str) -> Result<SubmittedTicket> {
         info!("正在提交工单...");
         let mut params = BTreeMap::new();
         params.insert("CategoryId".to_string(), category_id.to_string());
         params.insert("Severity".to_string(), "2".to_string()); // 2=紧急(业务受损)
         params.insert("Title".to_string(), self.config.ticket_title.clone());
-        params.insert(
-            "Description".to_string(),
-            self.config.ticket_description.clone(),
-        );
-
-        let resp_text = self.call_api("CreateTicket", &mut params).await?;
+        params.insert("Description".to_string(), description.to_string());
 
         // CreateTicket 返回的 Data 是工单 ID 字符串
-        let resp: ApiResponse<String> =
-            serde_json::from_str(&resp_text).context("解析 CreateTicket 响应失败")?;
-
-        if resp.success != Some(true) {
-            anyhow::bail!(
-                "CreateTicket 失败: {}",
-                resp.message.unwrap_or_default()
-            );
-        }
+        let resp: ApiResponse<String> = self.rpc.post_form("CreateTicket", &params).await?;
 
-        let ticket_id = resp.data.context("工单ID为空")?;
+        let request_id = resp.request_id.clone();
+        let ticket_id = resp.into_data("CreateTicket")?;
         info!("工单提交成功！工单号: {}", ticket_id);
-        Ok(ticket_id)
+        Ok(SubmittedTicket {
+            ticket_id,
+            request_id,
+        })
+    }
+
+    /// 调用 ListTickets 并解析分页结果
+    async fn query_tickets(&self, params: &BTreeMap<String, String>) -> Result<TicketPage> {
+        let resp: ApiResponse<TicketPage> = self.rpc.get("ListTickets", params).await?;
+        resp.into_data("ListTickets")
+    }
+
+    /// 分页查询工单列表，可按状态过滤（dealing / pending / completed / closed）
+    pub async fn list_tickets(
+        &self,
+        status: Option<&str>,
+        page: u32,
+        page_size: u32,
+    ) -> Result<(Vec<TicketInfo>, u64)> {
+        let mut params = BTreeMap::new();
+        params.insert("CurrentPage".to_string(), page.to_string());
+        params.insert("PageSize".to_string(), page_size.to_string());
+        if let Some(s) = status {
+            params.insert("TicketStatus".to_string(), s.to_string());
+        }
+
+        let page = self.query_tickets(&params).await?;
+        let list = page.list.unwrap_or_default();
+        let total = page.total.unwrap_or(list.len() as u64);
+        Ok((list, total))
+    }
+
+    /// 查询指定状态的全部工单（自动翻页）
+    pub async fn list_all_tickets(&self, status: &str) -> Result<Vec<TicketInfo>> {
+        let mut params = BTreeMap::new();
+        params.insert("TicketStatus".to_string(), status.to_string());
+        self.rpc
+            .get_all_pages(
+                "ListTickets",
+                &params,
+                "CurrentPage",
+                "PageSize",
+                50,
+                |resp: ApiResponse<TicketPage>| {
+                    let page = resp.into_data("ListTickets")?;
+                    Ok((page.list.unwrap_or_default(), page.total))
+                },
+            )
+            .await
+    }
+
+    /// 查询单个工单的当前状态（GetTicket）
+    pub async fn get_ticket(&self, ticket_id: &str) -> Result<TicketInfo> {
+        let mut params = BTreeMap::new();
+        params.insert("TicketId".to_string(), ticket_id.to_string());
+
+        let resp: ApiResponse<TicketInfo> = self.rpc.get("GetTicket", &params).await?;
+        let mut ticket = resp.into_data("GetTicket")?;
+        ticket.id.get_or_insert_with(|| ticket_id.to_string());
+        Ok(ticket)
     }
 
-    /// 执行完整的提交工单流程
-    pub async fn submit_ticket(&self) -> Result<String> {
+    /// 在已有工单上追加一条回复
+    pub async fn reply_ticket(&self, ticket_id: &str, content: &str) -> Result<()> {
+        info!("正在回复工单 {}...", ticket_id);
+        let mut params = BTreeMap::new();
+        params.insert("TicketId".to_string(), ticket_id.to_string());
+        params.insert("Content".to_string(), content.to_string());
+
+        let resp: ApiResponse<serde_json::Value> =
+            self.rpc.post_form("ReplyTicket", &params).await?;
+        resp.ensure_success("ReplyTicket")?;
+
+        info!("工单 {} 回复成功", ticket_id);
+        Ok(())
+    }
+
+    /// 关闭工单（带宽已恢复，问题已解决）
+    pub async fn close_ticket(&self, ticket_id: &str) -> Result<()> {
+        info!("正在关闭工单 {}...", ticket_id);
+        let mut params = BTreeMap::new();
+        params.insert("TicketId".to_string(), ticket_id.to_string());
+
+        let resp: ApiResponse<serde_json::Value> =
+            self.rpc.post_form("CloseTicket", &params).await?;
+        resp.ensure_success("CloseTicket")?;
+
+        info!("工单 {} 已关闭", ticket_id);
+        Ok(())
+    }
+
+    /// 查找本工具提交的、仍在处理中的限速工单
+    ///
+    /// 满足以下任一条件即视为本工具提交：工单号在 `known_ids` 中（当前正在跟踪），
+    /// 或标题来自内置模板 / 配置的 ticket_title。
+    /// 配置了 category_id 且返回数据带分类时，还要求分类一致。
+    pub async fn find_open_ticket(&self, known_ids: &[String]) -> Result<Option<TicketInfo>> {
+        for status in ["dealing", "pending"] {
+            let tickets = self.list_all_tickets(status).await?;
+            let found = tickets.into_iter().find(|t| {
+                let id = t.id.as_deref().unwrap_or_default();
+                let title = t.title.as_deref().unwrap_or_default();
+                let ours = known_ids.iter().any(|k| k == id)
+                    || templates::is_generated_title(title, &self.config.ticket_context())
+                    || title == self.config.ticket_title;
+                let same_category = self.config.category_id == 0
+                    || t.category_id.map(|c| c == self.config.category_id).unwrap_or(true);
+                ours && same_category
+            });
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(None)
+    }
+
+    /// 执行完整的提交工单流程，结果以 `source`（如 "feishu"）为来源写入历史记录
+    pub async fn submit_ticket(&self, source: &str) -> Result<SubmittedTicket> {
+        let _guard = SUBMIT_LOCK.lock().await;
+        let result = self.submit_ticket_unlocked().await;
+        history::record_ticket(&self.config.data_dir, source, &result);
+        result
+    }
+
+    async fn submit_ticket_unlocked(&self) -> Result<SubmittedTicket> {
+        // 0. 提交频率限制（所有入口共用）
+        governor::check(&self.config)?;
+        swas::check_traffic(&self.config).await?;
+
         // 1. 确定 ProductId
         let product_id = if self.config.product_id > 0 {
             info!("使用配置的 ProductId: {}", self.config.product_id);
@@ -281,7 +394,12 @@ impl WorkorderClient {
             self.find_category_id(product_id).await?
         };
 
-        // 3. 提交工单
-        self.create_ticket(category_id).await
+        // 3. 附上云监控带宽数据后提交工单
+        let description = format!(
+            "{}{}",
+            self.config.ticket_description,
+            cms::ticket_note(&self.config).await
+        );
+        self.create_ticket(category_id, &description).await
     }
 }
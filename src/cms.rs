@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Timelike};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::{CmsConfig, Config};
+use crate::rpc::AliyunRpcClient;
+use crate::swas::InstanceInfo;
+use crate::{notify, passive, templates};
+
+/// 云监控 API 版本
+const API_VERSION: &str = "2019-01-01";
+/// 每页最多返回的数据点数
+const PAGE_LENGTH: u32 = 1440;
+
+/// 云监控（CMS）API 客户端
+pub struct CmsClient {
+    rpc: AliyunRpcClient,
+}
+
+/// 一个统计周期内的带宽（Mbps）
+#[derive(Debug, Clone, Copy)]
+pub struct MetricPoint {
+    pub time: DateTime<Local>,
+    pub average: f64,
+    pub maximum: f64,
+}
+
+/// 一段时间内的公网出入带宽
+#[derive(Debug, Clone)]
+pub struct BandwidthReport {
+    /// 覆盖的时长（小时）
+    pub hours: u64,
+    /// 入方向（下载）
+    pub inbound: Vec<MetricPoint>,
+    /// 出方向（上传）
+    pub outbound: Vec<MetricPoint>,
+}
+
+/// 每小时汇总的一行：(整点时间, 入方向平均, 入方向峰值, 出方向平均, 出方向峰值)
+pub type HourlyRow = (DateTime<Local>, f64, f64, f64, f64);
+
+impl BandwidthReport {
+    /// 按整点汇总：平均取各周期平均值的均值，峰值取各周期最大值
+    pub fn hourly(&self) -> Vec<HourlyRow> {
+        let hour_of = |t: &DateTime<Local>| {
+            t.with_minute(0)
+                .and_then(|t| t.with_second(0))
+                .and_then(|t| t.with_nanosecond(0))
+                .unwrap_or(*t)
+        };
+        let summarize = |points: &[MetricPoint], hour: DateTime<Local>| {
+            let in_hour: Vec<&MetricPoint> =
+                points.iter().filter(|p| hour_of(&p.time) == hour).collect();
+            if in_hour.is_empty() {
+                return (0.0, 0.0);
+            }
+            let avg = in_hour.iter().map(|p| p.average).sum::<f64>() / in_hour.len() as f64;
+            let max = in_hour.iter().map(|p| p.maximum).fold(0.0, f64::max);
+            (avg, max)
+        };
+
+        let mut hours: Vec<DateTime<Local>> = self
+            .inbound
+            .iter()
+            .chain(self.outbound.iter())
+            .map(|p| hour_of(&p.time))
+            .collect();
+        hours.sort();
+        hours.dedup();
+
+        hours
+            .into_iter()
+            .map(|hour| {
+                let (in_avg, in_max) = summarize(&self.inbound, hour);
+                let (out_avg, out_max) = summarize(&self.outbound, hour);
+                (hour, in_avg, in_max, out_avg, out_max)
+            })
+            .collect()
+    }
+
+    /// 检测带宽平台：最近 `window` 个周期都有明显流量，且都卡在低于阈值的同一个值附近
+    ///
+    /// 判定规则与被动监测共用 [`passive::plateau_mean`]，返回 (方向, 平均 Mbps)。
+    pub fn plateau(&self, config: &Config, window: usize) -> Option<(&'static str, f64)> {
+        let settings = &config.passive;
+        let upload_threshold = config.upload_threshold.unwrap_or(config.speed_threshold);
+        let directions: [(&'static str, f64, &[MetricPoint]); 2] = [
+            ("下载", config.speed_threshold, &self.inbound),
+            ("上传", upload_threshold, &self.outbound),
+        ];
+
+        directions
+            .into_iter()
+            .find_map(|(direction, threshold, points)| {
+                if window == 0 || points.len() < window {
+                    return None;
+                }
+                let values: Vec<f64> = points[points.len() - window..]
+                    .iter()
+                    .map(|p| p.average)
+                    .collect();
+                passive::plateau_mean(&values, threshold, settings).map(|mbps| (direction, mbps))
+            })
+    }
+}
+
+// ---- API 响应结构 ----
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetricListResponse {
+    /// JSON 数组序列化成的字符串
+    datapoints: Option<String>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Datapoint {
+    timestamp: i64,
+    #[serde(rename = "Average")]
+    average: Option<f64>,
+    #[serde(rename = "Maximum")]
+    maximum: Option<f64>,
+}
+
+impl CmsClient {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            rpc: AliyunRpcClient::new(config, "metrics.aliyuncs.com", API_VERSION),
+        }
+    }
+
+    /// 查询一个指标最近 `hours` 小时的数据（自动翻页），单位从 bit/s 换算为 Mbps
+    async fn metric(
+        &self,
+        settings: &CmsConfig,
+        instance: &InstanceInfo,
+        metric_name: &str,
+    ) -> Result<Vec<MetricPoint>> {
+        let end = chrono::Utc::now().timestamp_millis();
+        let start = end - settings.hours as i64 * 3600 * 1000;
+
+        let mut params = BTreeMap::new();
+        params.insert("Namespace".to_string(), settings.namespace.clone());
+        params.insert("MetricName".to_string(), metric_name.to_string());
+        params.insert(
+            "Dimensions".to_string(),
+            serde_json::json!([{ "instanceId": instance.instance_id }]).to_string(),
+        );
+        params.insert("StartTime".to_string(), start.to_string());
+        params.insert("EndTime".to_string(), end.to_string());
+        params.insert("Period".to_string(), settings.period_secs.to_string());
+        params.insert("Length".to_string(), PAGE_LENGTH.to_string());
+
+        // 出错时 HTTP 状态码可能仍是 200，通用客户端会按 Success 字段判断
+        let rpc = self
+            .rpc
+            .with_endpoint(format!("metrics.{}.aliyuncs.com", instance.region_id));
+        let mut points = rpc
+            .get_all_by_token("DescribeMetricList", &params, |resp: MetricListResponse| {
+                let Some(raw) = resp.datapoints.as_deref().filter(|d| !d.is_empty()) else {
+                    return Ok((Vec::new(), resp.next_token));
+                };
+                let datapoints: Vec<Datapoint> =
+                    serde_json::from_str(raw).context("解析云监控数据点失败")?;
+                let points = datapoints
+                    .into_iter()
+                    .filter_map(|d| {
+                        let time = DateTime::from_timestamp_millis(d.timestamp)?.with_timezone(&Local);
+                        let average = d.average? / 1_000_000.0;
+                        Some(MetricPoint {
+                            time,
+                            average,
+                            maximum: d.maximum.map(|m| m / 1_000_000.0).unwrap_or(average),
+                        })
+                    })
+                    .collect();
+                Ok((points, resp.next_token))
+            })
+            .await?;
+        points.sort_by_key(|p| p.time);
+        Ok(points)
+    }
+
+    /// 查询实例最近一段时间的公网出入带宽
+    pub async fn bandwidth(&self, settings: &CmsConfig, instance: &InstanceInfo) -> Result<BandwidthReport> {
+        let inbound = self.metric(settings, instance, &settings.in_metric).await?;
+        let outbound = self.metric(settings, instance, &settings.out_metric).await?;
+        Ok(BandwidthReport {
+            hours: settings.hours,
+            inbound,
+            outbound,
+        })
+    }
+}
+
+/// 工单描述后附的云监控带宽表；未启用、未查询到实例或查询失败时返回空字符串
+pub async fn ticket_note(config: &Config) -> String {
+    let Some(instance) = config.instance.as_ref().filter(|_| config.cms.enabled) else {
+        return String::new();
+    };
+    match CmsClient::new(config).bandwidth(&config.cms, instance).await {
+        Ok(report) => templates::metric_table(report.hours, &report.hourly()),
+        Err(e) => {
+            warn!("查询云监控带宽失败，工单中不附带监控数据: {:#}", e);
+            String::new()
+        }
+    }
+}
+
+/// 定期查询云监控，带宽长时间卡在可疑的低值时通过 `trigger` 触发主动测速确认
+pub async fn run(config: Config, trigger: mpsc::Sender<()>) {
+    let Some(instance) = config.instance.clone() else {
+        return;
+    };
+    let settings = &config.cms;
+    let interval = Duration::from_secs(settings.check_interval_minutes.max(1) * 60);
+    // 平台至少覆盖被动监测的窗口长度
+    let window = ((config.passive.window_minutes * 60) / settings.period_secs.max(1)).max(2) as usize;
+    let cooldown = Duration::from_secs(config.passive.cooldown_minutes * 60);
+    let client = CmsClient::new(&config);
+    let mut last_trigger: Option<Instant> = None;
+    info!(
+        "云监控带宽检查已启动，每 {} 分钟查询一次",
+        settings.check_interval_minutes
+    );
+
+    loop {
+        tokio::time::sleep(interval).await;
+        let report = match client.bandwidth(settings, &instance).await {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("查询云监控带宽失败: {:#}", e);
+                continue;
+            }
+        };
+        let Some((direction, mbps)) = report.plateau(&config, window) else {
+            continue;
+        };
+        if last_trigger.is_some_and(|t| t.elapsed() < cooldown) {
+            continue;
+        }
+        last_trigger = Some(Instant::now());
+
+        warn!("云监控: {}带宽卡在约 {:.1} Mbps", direction, mbps);
+        let msg = format!(
+            "📉 云监控显示{}带宽最近一直卡在约 {:.1} Mbps，开始主动测速确认",
+            direction, mbps
+        );
+        notify::broadcast(&config, &msg).await;
+        if trigger.try_send(()).is_err() {
+            info!("已有检测在执行中，本次不再触发");
+        }
+    }
+}
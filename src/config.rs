@@ -1,40 +1,735 @@
-use anyhow::{Context, Result};
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
 use serde::Deserialize;
 use tracing::info;
 
+use crate::credentials::{CredentialProvider, DEFAULT_SESSION_NAME, DEFAULT_STS_ENDPOINT};
+use crate::secrets;
+use crate::signer::{AliyunSigner, SignatureAlgorithm};
+use crate::swas::InstanceInfo;
+use crate::templates::{self, TicketContext};
+
 /// JSON 配置文件结构（所有字段可选）
 #[derive(Debug, Default, Deserialize)]
 pub struct FileConfig {
     pub access_key_id: Option<String>,
     pub access_key_secret: Option<String>,
+    /// 从文件读取 access_key_secret（如 systemd credential、Docker secret）
+    pub access_key_secret_file: Option<String>,
+    pub security_token: Option<String>,
+    pub security_token_file: Option<String>,
+    pub assume_role: Option<AssumeRoleFileConfig>,
+    pub credentials: Option<CredentialsFileConfig>,
     pub product_id: Option<u64>,
     pub category_id: Option<u64>,
     pub ticket_title: Option<String>,
     pub ticket_description: Option<String>,
     pub cron_expression: Option<String>,
     pub speed_threshold: Option<f64>,
+    pub threshold_ratio: Option<f64>,
+    pub instance_id: Option<String>,
+    pub region_id: Option<String>,
+    /// 兼容旧配置，新配置请写在 speedtest.streams
+    pub speedtest_streams: Option<usize>,
+    pub upload_threshold: Option<f64>,
+    pub speedtest: Option<SpeedtestFileConfig>,
+    pub probe: Option<ProbeFileConfig>,
+    pub passive: Option<PassiveFileConfig>,
+    pub cms: Option<CmsFileConfig>,
+    pub api: Option<ApiFileConfig>,
     pub feishu_webhook_url: Option<String>,
+    pub feishu_webhook_url_file: Option<String>,
     pub callback_url: Option<String>,
     pub callback_port: Option<u16>,
     pub callback_secret: Option<String>,
+    pub callback_secret_file: Option<String>,
+    pub serve_speedtest: Option<bool>,
     pub auto_submit: Option<bool>,
     pub telegram_bot_token: Option<String>,
+    pub telegram_bot_token_file: Option<String>,
     pub telegram_chat_id: Option<i64>,
+    pub ticket_poll_interval: Option<u64>,
+    pub data_dir: Option<String>,
+    pub followup_window_hours: Option<u64>,
+    pub recovery_checks: Option<u32>,
+    pub min_submit_interval_minutes: Option<u64>,
+    pub max_tickets_per_day: Option<u32>,
+    pub max_tickets_per_week: Option<u32>,
+    pub confirm_samples: Option<u32>,
+    pub confirm_interval_secs: Option<u64>,
+    pub confirm_quorum: Option<u32>,
+    pub congestion_confidence: Option<f64>,
 }
 
-/// 应用配置
+/// 配置文件中的 speedtest 段
+#[derive(Debug, Default, Deserialize)]
+pub struct SpeedtestFileConfig {
+    pub backend: Option<String>,
+    pub iperf3_server: Option<String>,
+    pub download_urls: Option<Vec<String>>,
+    pub upload_url: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub timeout_secs: Option<u64>,
+    pub user_agent: Option<String>,
+    pub peer: Option<String>,
+    pub peer_secret: Option<String>,
+    pub peer_secret_file: Option<String>,
+    pub streams: Option<usize>,
+    pub bind_interface: Option<String>,
+    pub source_ip: Option<std::net::IpAddr>,
+}
+
+/// 测速数据来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedtestBackend {
+    /// HTTP 下载 / 上传（Cloudflare 或兼容的测速地址）
+    Http,
+    /// 原生 iperf3 协议，连接现有的 iperf3 服务端
+    Iperf3,
+}
+
+impl SpeedtestBackend {
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "http" => Ok(Self::Http),
+            "iperf3" => Ok(Self::Iperf3),
+            other => anyhow::bail!("未知的测速方式 {}，可选 http 或 iperf3", other),
+        }
+    }
+}
+
+/// 测速目标与参数
+#[derive(Clone)]
+pub struct SpeedtestConfig {
+    /// 测速数据来源
+    pub backend: SpeedtestBackend,
+    /// iperf3 服务端地址（host 或 host:port，默认端口 5201）
+    pub iperf3_server: Option<String>,
+    /// 下载测速 URL 列表（按优先级排列，多线程时各连接从不同 URL 开始）
+    pub download_urls: Vec<String>,
+    /// 上传测速地址（接收 POST 数据）
+    pub upload_url: String,
+    /// 从开始传输数据起的计时时长（秒）
+    pub duration_secs: u64,
+    /// 迟迟没有数据时的超时时间（秒）
+    pub timeout_secs: u64,
+    /// 测速请求的 User-Agent
+    pub user_agent: String,
+    /// 测速的并发连接数（1 为单线程）
+    pub streams: usize,
+    /// 绑定的网卡名（如 eth1），多网卡时指定测哪条线路
+    pub bind_interface: Option<String>,
+    /// 绑定的源 IP
+    pub source_ip: Option<std::net::IpAddr>,
+}
+
+/// 隐去 URL 中 `secret` 参数的值（对端测速地址会带上 peer_secret）
+fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("secret", _)) => "secret=***",
+            _ => pair,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", base, query)
+}
+
+impl fmt::Debug for SpeedtestConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let download_urls: Vec<String> = self.download_urls.iter().map(|u| redact_url(u)).collect();
+        f.debug_struct("SpeedtestConfig")
+            .field("backend", &self.backend)
+            .field("iperf3_server", &self.iperf3_server)
+            .field("download_urls", &download_urls)
+            .field("upload_url", &redact_url(&self.upload_url))
+            .field("duration_secs", &self.duration_secs)
+            .field("timeout_secs", &self.timeout_secs)
+            .field("user_agent", &self.user_agent)
+            .field("streams", &self.streams)
+            .field("bind_interface", &self.bind_interface)
+            .field("source_ip", &self.source_ip)
+            .finish()
+    }
+}
+
+impl SpeedtestConfig {
+    fn load(file_cfg: SpeedtestFileConfig, legacy_streams: Option<usize>) -> Result<Self> {
+        let backend = match std::env::var("SPEEDTEST_BACKEND").ok().or(file_cfg.backend) {
+            Some(v) => SpeedtestBackend::parse(&v)?,
+            None => SpeedtestBackend::Http,
+        };
+
+        let iperf3_server = std::env::var("IPERF3_SERVER")
+            .ok()
+            .or(file_cfg.iperf3_server);
+        if backend == SpeedtestBackend::Iperf3 && iperf3_server.is_none() {
+            anyhow::bail!("测速方式为 iperf3 时必须设置 speedtest.iperf3_server 或环境变量 IPERF3_SERVER");
+        }
+
+        // 指定了对端测速服务时，默认的下载 / 上传地址改为对端的 /__down 和 /__up
+        let peer = std::env::var("SPEEDTEST_PEER")
+            .ok()
+            .or(file_cfg.peer)
+            .map(|p| p.trim_end_matches('/').to_string());
+        let peer_secret = secrets::resolve(
+            "speedtest.peer_secret",
+            &["SPEEDTEST_PEER_SECRET"],
+            &file_cfg.peer_secret,
+            &file_cfg.peer_secret_file,
+        )?;
+        let peer_url = |path: &str, query: &str| {
+            peer.as_ref().map(|base| {
+                let mut url = format!("{}{}", base, path);
+                let mut params: Vec<String> = Vec::new();
+                if !query.is_empty() {
+                    params.push(query.to_string());
+                }
+                if let Some(secret) = &peer_secret {
+                    params.push(format!("secret={}", AliyunSigner::percent_encode(secret)));
+                }
+                if !params.is_empty() {
+                    url.push('?');
+                    url.push_str(&params.join("&"));
+                }
+                url
+            })
+        };
+
+        let download_urls = std::env::var("SPEEDTEST_URLS")
+            .ok()
+            .map(|v| {
+                v.split(',')
+                    .map(|u| u.trim().to_string())
+                    .filter(|u| !u.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .or(file_cfg.download_urls)
+            .filter(|urls| !urls.is_empty())
+            .or_else(|| peer_url("/__down", "bytes=104857600").map(|u| vec![u]))
+            .unwrap_or_else(|| {
+                crate::speedtest::DEFAULT_DOWNLOAD_URLS
+                    .iter()
+                    .map(|u| u.to_string())
+                    .collect()
+            });
+
+        let upload_url = std::env::var("UPLOAD_URL")
+            .ok()
+            .or(file_cfg.upload_url)
+            .or_else(|| peer_url("/__up", ""))
+            .unwrap_or_else(|| crate::speedtest::DEFAULT_UPLOAD_URL.to_string());
+
+        let duration_secs = std::env::var("SPEEDTEST_DURATION_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.duration_secs)
+            .unwrap_or(10);
+
+        let timeout_secs = std::env::var("SPEEDTEST_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.timeout_secs)
+            .unwrap_or(30);
+
+        let user_agent = std::env::var("SPEEDTEST_USER_AGENT")
+            .ok()
+            .or(file_cfg.user_agent)
+            .unwrap_or_else(|| "Mozilla/5.0".to_string());
+
+        let streams = std::env::var("SPEEDTEST_STREAMS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.streams)
+            .or(legacy_streams)
+            .unwrap_or(1);
+
+        let bind_interface = std::env::var("SPEEDTEST_INTERFACE")
+            .ok()
+            .or(file_cfg.bind_interface);
+
+        let source_ip = std::env::var("SPEEDTEST_SOURCE_IP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.source_ip);
+
+        Ok(Self {
+            backend,
+            iperf3_server,
+            download_urls,
+            upload_url,
+            duration_secs,
+            timeout_secs,
+            user_agent,
+            streams,
+            bind_interface,
+            source_ip,
+        })
+    }
+}
+
+/// 配置文件中的 probe 段
+#[derive(Debug, Default, Deserialize)]
+pub struct ProbeFileConfig {
+    pub targets: Option<Vec<String>>,
+    pub http_urls: Option<Vec<String>>,
+    pub count: Option<u32>,
+    pub interval_ms: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub max_avg_ms: Option<f64>,
+    pub max_p95_ms: Option<f64>,
+    pub max_jitter_ms: Option<f64>,
+    pub max_loss_ratio: Option<f64>,
+}
+
+/// 延迟探测参数
 #[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// TCP 连接探测目标（host:port）
+    pub targets: Vec<String>,
+    /// HTTP 首字节时间探测地址
+    pub http_urls: Vec<String>,
+    /// 每个目标探测次数
+    pub count: u32,
+    /// 同一目标两次探测的间隔（毫秒）
+    pub interval_ms: u64,
+    /// 单次探测超时（毫秒），超时计为失败
+    pub timeout_ms: u64,
+    /// 平均延迟告警阈值（毫秒）
+    pub max_avg_ms: Option<f64>,
+    /// P95 延迟告警阈值（毫秒）
+    pub max_p95_ms: Option<f64>,
+    /// 抖动告警阈值（毫秒）
+    pub max_jitter_ms: Option<f64>,
+    /// 连接失败比例告警阈值（0~1）
+    pub max_loss_ratio: Option<f64>,
+}
+
+impl ProbeConfig {
+    fn load(file_cfg: ProbeFileConfig) -> Self {
+        let list = |v: String| -> Vec<String> {
+            v.split(',')
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .collect()
+        };
+        let float = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+
+        Self {
+            targets: std::env::var("PROBE_TARGETS")
+                .ok()
+                .map(list)
+                .or(file_cfg.targets)
+                .unwrap_or_default(),
+            http_urls: std::env::var("PROBE_HTTP_URLS")
+                .ok()
+                .map(list)
+                .or(file_cfg.http_urls)
+                .unwrap_or_default(),
+            count: std::env::var("PROBE_COUNT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.count)
+                .unwrap_or(10),
+            interval_ms: std::env::var("PROBE_INTERVAL_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.interval_ms)
+                .unwrap_or(200),
+            timeout_ms: std::env::var("PROBE_TIMEOUT_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.timeout_ms)
+                .unwrap_or(2000),
+            max_avg_ms: float("PROBE_MAX_AVG_MS").or(file_cfg.max_avg_ms),
+            max_p95_ms: float("PROBE_MAX_P95_MS").or(file_cfg.max_p95_ms),
+            max_jitter_ms: float("PROBE_MAX_JITTER_MS").or(file_cfg.max_jitter_ms),
+            max_loss_ratio: float("PROBE_MAX_LOSS_RATIO").or(file_cfg.max_loss_ratio),
+        }
+    }
+
+    /// 是否配置了探测目标
+    pub fn enabled(&self) -> bool {
+        !self.targets.is_empty() || !self.http_urls.is_empty()
+    }
+}
+
+/// 配置文件中的 passive 段
+#[derive(Debug, Default, Deserialize)]
+pub struct PassiveFileConfig {
+    pub interface: Option<String>,
+    pub sample_interval_secs: Option<u64>,
+    pub window_minutes: Option<u64>,
+    pub min_busy_mbps: Option<f64>,
+    pub tolerance: Option<f64>,
+    pub plateau_mbps: Option<f64>,
+    pub cooldown_minutes: Option<u64>,
+}
+
+/// 被动带宽监测参数
+#[derive(Debug, Clone)]
+pub struct PassiveConfig {
+    /// 监测的网卡名（如 eth0），不设置则不启用
+    pub interface: Option<String>,
+    /// 采样间隔（秒）
+    pub sample_interval_secs: u64,
+    /// 滚动窗口长度（分钟），流量持续卡住这么久才算平台
+    pub window_minutes: u64,
+    /// 每个采样至少要有这么多流量（Mbps），避免把空闲当成限速
+    pub min_busy_mbps: f64,
+    /// 速率波动容差（相对平均值的比例）
+    pub tolerance: f64,
+    /// 可疑的限速值（Mbps），设置后只有卡在它附近才算平台
+    pub plateau_mbps: Option<f64>,
+    /// 两次因平台触发主动测速的最小间隔（分钟）
+    pub cooldown_minutes: u64,
+}
+
+impl PassiveConfig {
+    fn load(file_cfg: PassiveFileConfig) -> Self {
+        Self {
+            interface: std::env::var("PASSIVE_INTERFACE")
+                .ok()
+                .or(file_cfg.interface),
+            sample_interval_secs: std::env::var("PASSIVE_SAMPLE_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.sample_interval_secs)
+                .unwrap_or(5),
+            window_minutes: std::env::var("PASSIVE_WINDOW_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.window_minutes)
+                .unwrap_or(5),
+            min_busy_mbps: std::env::var("PASSIVE_MIN_BUSY_MBPS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.min_busy_mbps)
+                .unwrap_or(2.0),
+            tolerance: std::env::var("PASSIVE_TOLERANCE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.tolerance)
+                .unwrap_or(0.15),
+            plateau_mbps: std::env::var("PASSIVE_PLATEAU_MBPS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.plateau_mbps),
+            cooldown_minutes: std::env::var("PASSIVE_COOLDOWN_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.cooldown_minutes)
+                .unwrap_or(60),
+        }
+    }
+}
+
+/// 配置文件中的 cms 段
+#[derive(Debug, Default, Deserialize)]
+pub struct CmsFileConfig {
+    pub enabled: Option<bool>,
+    pub namespace: Option<String>,
+    pub in_metric: Option<String>,
+    pub out_metric: Option<String>,
+    pub hours: Option<u64>,
+    pub period_secs: Option<u64>,
+    pub check_interval_minutes: Option<u64>,
+}
+
+/// 云监控带宽数据参数
+#[derive(Debug, Clone)]
+pub struct CmsConfig {
+    /// 是否查询云监控（还需要配置 instance_id）
+    pub enabled: bool,
+    /// 指标所在的命名空间
+    pub namespace: String,
+    /// 公网入方向带宽指标名
+    pub in_metric: String,
+    /// 公网出方向带宽指标名
+    pub out_metric: String,
+    /// 查询最近多少小时的数据
+    pub hours: u64,
+    /// 统计周期（秒）
+    pub period_secs: u64,
+    /// 后台检查带宽平台的间隔（分钟），0 表示只在工单中附带数据
+    pub check_interval_minutes: u64,
+}
+
+impl CmsConfig {
+    fn load(file_cfg: CmsFileConfig) -> Self {
+        Self {
+            enabled: std::env::var("CMS_ENABLED")
+                .ok()
+                .map(|v| v == "true" || v == "1")
+                .or(file_cfg.enabled)
+                .unwrap_or(false),
+            namespace: std::env::var("CMS_NAMESPACE")
+                .ok()
+                .or(file_cfg.namespace)
+                .unwrap_or_else(|| "acs_swas".to_string()),
+            in_metric: std::env::var("CMS_IN_METRIC")
+                .ok()
+                .or(file_cfg.in_metric)
+                .unwrap_or_else(|| "InternetInRate".to_string()),
+            out_metric: std::env::var("CMS_OUT_METRIC")
+                .ok()
+                .or(file_cfg.out_metric)
+                .unwrap_or_else(|| "InternetOutRate".to_string()),
+            hours: std::env::var("CMS_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.hours)
+                .unwrap_or(6),
+            period_secs: std::env::var("CMS_PERIOD_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.period_secs)
+                .unwrap_or(300),
+            check_interval_minutes: std::env::var("CMS_CHECK_INTERVAL_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.check_interval_minutes)
+                .unwrap_or(30),
+        }
+    }
+}
+
+/// 配置文件中的 api 段
+#[derive(Debug, Default, Deserialize)]
+pub struct ApiFileConfig {
+    pub endpoint: Option<String>,
+    pub scheme: Option<String>,
+    pub max_retries: Option<u32>,
+    pub timeout_secs: Option<u64>,
+    pub signature_algorithm: Option<String>,
+}
+
+/// 阿里云 API 调用参数
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    /// 工单 API 的请求协议，`https` 或 `http`（连接本地模拟服务时使用），其他阿里云 API 始终走 https
+    pub scheme: String,
+    /// 超时、限流、5xx 等临时错误的最大重试次数（0 表示不重试）
+    pub max_retries: u32,
+    /// 单次请求的超时时间（秒）
+    pub timeout_secs: u64,
+    /// V3 签名算法
+    pub signature_algorithm: SignatureAlgorithm,
+}
+
+impl ApiConfig {
+    fn load(file_cfg: &ApiFileConfig) -> Result<Self> {
+        let scheme = std::env::var("API_SCHEME")
+            .ok()
+            .or(file_cfg.scheme.clone())
+            .unwrap_or_else(|| "https".to_string())
+            .to_lowercase();
+        if scheme != "https" && scheme != "http" {
+            anyhow::bail!("api.scheme 只能是 https 或 http，当前为 {}", scheme);
+        }
+        let signature_algorithm = match std::env::var("API_SIGNATURE_ALGORITHM")
+            .ok()
+            .or(file_cfg.signature_algorithm.clone())
+        {
+            Some(v) => SignatureAlgorithm::parse(&v)?,
+            None => SignatureAlgorithm::HmacSha256,
+        };
+        Ok(Self {
+            scheme,
+            max_retries: std::env::var("API_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.max_retries)
+                .unwrap_or(3),
+            timeout_secs: std::env::var("API_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.timeout_secs)
+                .unwrap_or(30),
+            signature_algorithm,
+        })
+    }
+}
+
+/// 配置文件中的 credentials 段
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CredentialsFileConfig {
+    pub profile: Option<String>,
+    pub cli_config_file: Option<String>,
+    pub ecs_ram_role: Option<String>,
+    pub ecs_metadata_endpoint: Option<String>,
+    pub uri: Option<String>,
+}
+
+/// 凭证链各来源的参数
+#[derive(Clone)]
+pub struct CredentialsConfig {
+    /// 环境变量或 config.json 中的 AccessKey
+    pub access_key_id: Option<String>,
+    pub access_key_secret: Option<String>,
+    pub security_token: Option<String>,
+    /// aliyun CLI 配置中使用的 profile，不设置则用文件中的 current
+    pub profile: Option<String>,
+    /// aliyun CLI 配置文件路径
+    pub cli_config_file: String,
+    /// ECS 实例 RAM 角色名，空字符串表示自动查询，不设置则不使用
+    pub ecs_ram_role: Option<String>,
+    /// ECS 元数据服务地址
+    pub ecs_metadata_endpoint: String,
+    /// 凭证服务 URI
+    pub uri: Option<String>,
+}
+
+impl fmt::Debug for CredentialsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialsConfig")
+            .field("access_key_id", &self.access_key_id)
+            .field("access_key_secret", &redact(&self.access_key_secret))
+            .field("security_token", &redact(&self.security_token))
+            .field("profile", &self.profile)
+            .field("cli_config_file", &self.cli_config_file)
+            .field("ecs_ram_role", &self.ecs_ram_role)
+            .field("ecs_metadata_endpoint", &self.ecs_metadata_endpoint)
+            .field("uri", &self.uri)
+            .finish()
+    }
+}
+
+impl CredentialsConfig {
+    /// 环境变量同时支持本工具的 ALIYUN_* 和官方 SDK 的 ALIBABA_CLOUD_* 两套名字
+    fn load(file_cfg: &FileConfig) -> Result<Self> {
+        let env = |names: &[&str]| names.iter().find_map(|n| std::env::var(n).ok());
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        let access_key_secret = secrets::resolve(
+            "access_key_secret",
+            &["ALIYUN_ACCESS_KEY_SECRET", "ALIBABA_CLOUD_ACCESS_KEY_SECRET"],
+            &file_cfg.access_key_secret,
+            &file_cfg.access_key_secret_file,
+        )?;
+        let security_token = secrets::resolve(
+            "security_token",
+            &["ALIYUN_SECURITY_TOKEN", "ALIBABA_CLOUD_SECURITY_TOKEN"],
+            &file_cfg.security_token,
+            &file_cfg.security_token_file,
+        )?;
+        let access_key_id = env(&["ALIYUN_ACCESS_KEY_ID", "ALIBABA_CLOUD_ACCESS_KEY_ID"])
+            .or(file_cfg.access_key_id.clone())
+            .filter(|v| !v.is_empty());
+        let file_cfg = file_cfg.credentials.clone().unwrap_or_default();
+        Ok(Self {
+            access_key_id,
+            access_key_secret: access_key_secret.filter(|v| !v.is_empty()),
+            security_token: security_token.filter(|v| !v.is_empty()),
+            profile: env(&["ALIBABA_CLOUD_PROFILE"]).or(file_cfg.profile),
+            cli_config_file: env(&["ALIBABA_CLOUD_CONFIG_FILE"])
+                .or(file_cfg.cli_config_file)
+                .unwrap_or_else(|| format!("{}/.aliyun/config.json", home)),
+            ecs_ram_role: env(&["ALIBABA_CLOUD_ECS_METADATA"]).or(file_cfg.ecs_ram_role),
+            ecs_metadata_endpoint: env(&["ALIBABA_CLOUD_ECS_METADATA_ENDPOINT"])
+                .or(file_cfg.ecs_metadata_endpoint)
+                .unwrap_or_else(|| "http://100.100.100.200".to_string()),
+            uri: env(&["ALIBABA_CLOUD_CREDENTIALS_URI"])
+                .or(file_cfg.uri)
+                .filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+/// 配置文件中的 assume_role 段
+#[derive(Debug, Default, Deserialize)]
+pub struct AssumeRoleFileConfig {
+    pub role_arn: Option<String>,
+    pub session_name: Option<String>,
+    pub duration_secs: Option<u64>,
+    pub policy: Option<String>,
+    pub endpoint: Option<String>,
+}
+
+/// AssumeRole 参数：用权限最小的基础 AccessKey 换取角色的临时凭证
+#[derive(Debug, Clone)]
+pub struct AssumeRoleConfig {
+    /// 角色 ARN，如 acs:ram::123456789012****:role/auto-ticket
+    pub role_arn: String,
+    pub session_name: String,
+    /// 临时凭证有效期（秒），900 ~ 角色的最大会话时间
+    pub duration_secs: u64,
+    /// 进一步限制临时凭证权限的策略（JSON），不设置则拥有角色的全部权限
+    pub policy: Option<String>,
+    /// STS 接入地址
+    pub endpoint: String,
+}
+
+impl AssumeRoleConfig {
+    /// 未配置 role_arn 时返回 None（不扮演角色）
+    fn load(file_cfg: AssumeRoleFileConfig) -> Option<Self> {
+        let role_arn = std::env::var("ALIYUN_ROLE_ARN")
+            .ok()
+            .or(file_cfg.role_arn)
+            .filter(|r| !r.is_empty())?;
+        Some(Self {
+            role_arn,
+            session_name: std::env::var("ALIYUN_ROLE_SESSION_NAME")
+                .ok()
+                .or(file_cfg.session_name)
+                .unwrap_or_else(|| DEFAULT_SESSION_NAME.to_string()),
+            duration_secs: std::env::var("ALIYUN_ROLE_DURATION_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .or(file_cfg.duration_secs)
+                .unwrap_or(3600),
+            policy: std::env::var("ALIYUN_ROLE_POLICY")
+                .ok()
+                .or(file_cfg.policy),
+            endpoint: std::env::var("ALIYUN_STS_ENDPOINT")
+                .ok()
+                .or(file_cfg.endpoint)
+                .unwrap_or_else(|| DEFAULT_STS_ENDPOINT.to_string()),
+        })
+    }
+}
+
+/// 应用配置
+#[derive(Clone)]
 pub struct Config {
-    pub access_key_id: String,
-    pub access_key_secret: String,
+    /// 实际调用 API 使用的凭证（AccessKey、STS 临时凭证或 AssumeRole 获取的角色凭证）
+    pub credentials: Arc<CredentialProvider>,
+    /// 工单 API 地址（host 或 host:port）
     pub endpoint: String,
     pub api_version: String,
+    /// 阿里云 API 协议、重试与超时
+    pub api: ApiConfig,
     pub product_id: u64,
     pub category_id: u64,
     pub ticket_title: String,
     pub ticket_description: String,
     pub cron_expression: String,
     pub speed_threshold: f64,
+    /// 是否手动设置了 speed_threshold（否则按实例套餐带宽推算）
+    pub speed_threshold_configured: bool,
+    /// 按套餐带宽推算阈值时的比例
+    pub threshold_ratio: f64,
+    /// 轻量应用服务器实例 ID，设置后从 SWAS 查询地域和套餐带宽
+    pub instance_id: Option<String>,
+    /// 实例所在地域（如 cn-hongkong），不设置则自动查找
+    pub region_id: Option<String>,
+    /// 从 SWAS 查到的实例信息（启动时填充）
+    pub instance: Option<InstanceInfo>,
+    /// 上传限速阈值（Mbps），不设置则不测上传
+    pub upload_threshold: Option<f64>,
+    /// 测速目标与参数
+    pub speedtest: SpeedtestConfig,
+    /// 延迟 / 抖动 / 丢包探测
+    pub probe: ProbeConfig,
+    /// 被动带宽监测
+    pub passive: PassiveConfig,
+    /// 云监控带宽数据
+    pub cms: CmsConfig,
     /// 飞书群机器人 Webhook URL
     pub feishu_webhook_url: Option<String>,
     /// 回调服务的公网基础 URL，如 https://example.com:9876/ticket
@@ -43,27 +738,97 @@ pub struct Config {
     pub callback_port: u16,
     /// 手动触发和审批的鉴权密钥
     pub callback_secret: Option<String>,
+    /// 回调服务是否同时提供对端测速接口（/__down、/__up）
+    pub serve_speedtest: bool,
     /// 限速时是否自动提交工单（不等审批）
     pub auto_submit: bool,
     /// Telegram Bot Token（通过 @BotFather 获取）
     pub telegram_bot_token: Option<String>,
     /// 允许操作 Bot 的 Telegram 用户 ID
     pub telegram_chat_id: Option<i64>,
+    /// 工单状态轮询间隔（秒）
+    pub ticket_poll_interval: u64,
+    /// 数据目录（存放历史记录等）
+    pub data_dir: String,
+    /// 工单被标记为已处理后，多少小时内仍限速则在原工单追加回复
+    pub followup_window_hours: u64,
+    /// 工单提交后连续多少次测速正常即自动关闭工单（0 表示不自动关闭）
+    pub recovery_checks: u32,
+    /// 两次提交工单之间的最小间隔（分钟）
+    pub min_submit_interval_minutes: u64,
+    /// 滚动 24 小时内最多提交的工单数
+    pub max_tickets_per_day: u32,
+    /// 滚动 7 天内最多提交的工单数（不设置则不限制）
+    pub max_tickets_per_week: Option<u32>,
+    /// 首次测速低于阈值后的复测次数（0 表示不复测）
+    pub confirm_samples: u32,
+    /// 复测间隔（秒）
+    pub confirm_interval_secs: u64,
+    /// 至少多少次采样低于阈值才判定限速（不设置则按中位数判定）
+    pub confirm_quorum: Option<u32>,
+    /// 吞吐曲线判定为拥塞的置信度达到多少时不提交工单（大于 1 表示从不跳过）
+    pub congestion_confidence: f64,
+}
+
+/// 敏感字段只显示是否设置，避免打印配置时泄露
+fn redact(value: &Option<String>) -> Option<&'static str> {
+    value.as_ref().map(|_| "***")
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("credentials", &self.credentials)
+            .field("endpoint", &self.endpoint)
+            .field("api_version", &self.api_version)
+            .field("api", &self.api)
+            .field("product_id", &self.product_id)
+            .field("category_id", &self.category_id)
+            .field("ticket_title", &self.ticket_title)
+            .field("ticket_description", &self.ticket_description)
+            .field("cron_expression", &self.cron_expression)
+            .field("speed_threshold", &self.speed_threshold)
+            .field("speed_threshold_configured", &self.speed_threshold_configured)
+            .field("threshold_ratio", &self.threshold_ratio)
+            .field("instance_id", &self.instance_id)
+            .field("region_id", &self.region_id)
+            .field("instance", &self.instance)
+            .field("upload_threshold", &self.upload_threshold)
+            .field("speedtest", &self.speedtest)
+            .field("probe", &self.probe)
+            .field("passive", &self.passive)
+            .field("cms", &self.cms)
+            .field("feishu_webhook_url", &redact(&self.feishu_webhook_url))
+            .field("callback_url", &self.callback_url)
+            .field("callback_port", &self.callback_port)
+            .field("callback_secret", &redact(&self.callback_secret))
+            .field("serve_speedtest", &self.serve_speedtest)
+            .field("auto_submit", &self.auto_submit)
+            .field("telegram_bot_token", &redact(&self.telegram_bot_token))
+            .field("telegram_chat_id", &self.telegram_chat_id)
+            .field("ticket_poll_interval", &self.ticket_poll_interval)
+            .field("data_dir", &self.data_dir)
+            .field("followup_window_hours", &self.followup_window_hours)
+            .field("recovery_checks", &self.recovery_checks)
+            .field("min_submit_interval_minutes", &self.min_submit_interval_minutes)
+            .field("max_tickets_per_day", &self.max_tickets_per_day)
+            .field("max_tickets_per_week", &self.max_tickets_per_week)
+            .field("confirm_samples", &self.confirm_samples)
+            .field("confirm_interval_secs", &self.confirm_interval_secs)
+            .field("confirm_quorum", &self.confirm_quorum)
+            .field("congestion_confidence", &self.congestion_confidence)
+            .finish()
+    }
 }
 
 impl Config {
     pub fn load() -> Result<Self> {
-        let file_cfg = Self::load_file();
-
-        let access_key_id = std::env::var("ALIYUN_ACCESS_KEY_ID")
-            .ok()
-            .or(file_cfg.access_key_id)
-            .context("缺少 access_key_id，请在 config.json 或环境变量 ALIYUN_ACCESS_KEY_ID 中设置")?;
+        Self::from_file(Self::load_file())
+    }
 
-        let access_key_secret = std::env::var("ALIYUN_ACCESS_KEY_SECRET")
-            .ok()
-            .or(file_cfg.access_key_secret)
-            .context("缺少 access_key_secret，请在 config.json 或环境变量 ALIYUN_ACCESS_KEY_SECRET 中设置")?;
+    /// 以给定的配置文件内容加载配置（环境变量仍然优先）
+    pub fn from_file(file_cfg: FileConfig) -> Result<Self> {
+        let credentials_settings = CredentialsConfig::load(&file_cfg)?;
 
         let product_id = std::env::var("TICKET_PRODUCT_ID")
             .ok()
@@ -80,36 +845,74 @@ impl Config {
         let ticket_title = std::env::var("TICKET_TITLE")
             .ok()
             .or(file_cfg.ticket_title)
-            .unwrap_or_else(|| "香港轻量应用服务器带宽被限速，请帮忙检查解除".to_string());
+            .unwrap_or_else(|| templates::default_title(&TicketContext::default()));
 
         let ticket_description = std::env::var("TICKET_DESCRIPTION")
             .ok()
             .or(file_cfg.ticket_description)
-            .unwrap_or_else(|| {
-                concat!(
-                    "您好，我购买的香港轻量应用服务器带宽为30Mbps，",
-                    "但目前实际带宽被限制在约10Mbps左右。",
-                    "请帮忙检查服务器是否存在带宽限速情况，",
-                    "如果存在限速请帮忙解除，恢复到购买时承诺的30Mbps带宽。",
-                    "谢谢！"
-                )
-                .to_string()
-            });
+            .unwrap_or_else(|| templates::default_description(&TicketContext::default()));
 
         let cron_expression = std::env::var("CRON_EXPRESSION")
             .ok()
             .or(file_cfg.cron_expression)
             .unwrap_or_else(|| "0 0 9 * * *".to_string());
 
-        let speed_threshold = std::env::var("SPEED_THRESHOLD")
+        let configured_threshold: Option<f64> = std::env::var("SPEED_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.speed_threshold);
+        let speed_threshold_configured = configured_threshold.is_some();
+        let speed_threshold = configured_threshold.unwrap_or(20.0);
+
+        let threshold_ratio = std::env::var("THRESHOLD_RATIO")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.threshold_ratio)
+            .unwrap_or(0.66);
+
+        let instance_id = std::env::var("SWAS_INSTANCE_ID")
+            .ok()
+            .or(file_cfg.instance_id);
+
+        let region_id = std::env::var("SWAS_REGION_ID")
+            .ok()
+            .or(file_cfg.region_id);
+
+        let upload_threshold = std::env::var("UPLOAD_THRESHOLD")
             .ok()
             .and_then(|v| v.parse().ok())
-            .or(file_cfg.speed_threshold)
-            .unwrap_or(20.0);
+            .or(file_cfg.upload_threshold);
+
+        let speedtest = SpeedtestConfig::load(
+            file_cfg.speedtest.unwrap_or_default(),
+            file_cfg.speedtest_streams,
+        )?;
+
+        let probe = ProbeConfig::load(file_cfg.probe.unwrap_or_default());
 
-        let feishu_webhook_url = std::env::var("FEISHU_WEBHOOK_URL")
+        let passive = PassiveConfig::load(file_cfg.passive.unwrap_or_default());
+
+        let cms = CmsConfig::load(file_cfg.cms.unwrap_or_default());
+        let api_file_cfg = file_cfg.api.unwrap_or_default();
+        let api = ApiConfig::load(&api_file_cfg)?;
+        let endpoint = std::env::var("WORKORDER_ENDPOINT")
             .ok()
-            .or(file_cfg.feishu_webhook_url);
+            .or(api_file_cfg.endpoint)
+            .unwrap_or_else(|| "workorder.aliyuncs.com".to_string());
+
+        // 按凭证链查找凭证；配置了 role_arn 时再用找到的凭证扮演角色
+        let credentials = Arc::new(CredentialProvider::from_chain(
+            &credentials_settings,
+            AssumeRoleConfig::load(file_cfg.assume_role.unwrap_or_default()),
+            api.clone(),
+        )?);
+
+        let feishu_webhook_url = secrets::resolve(
+            "feishu_webhook_url",
+            &["FEISHU_WEBHOOK_URL"],
+            &file_cfg.feishu_webhook_url,
+            &file_cfg.feishu_webhook_url_file,
+        )?;
 
         let callback_url = std::env::var("CALLBACK_URL")
             .ok()
@@ -121,9 +924,21 @@ impl Config {
             .or(file_cfg.callback_port)
             .unwrap_or(9876);
 
-        let callback_secret = std::env::var("CALLBACK_SECRET")
+        let callback_secret = secrets::resolve(
+            "callback_secret",
+            &["CALLBACK_SECRET"],
+            &file_cfg.callback_secret,
+            &file_cfg.callback_secret_file,
+        )?;
+
+        let serve_speedtest = std::env::var("SERVE_SPEEDTEST")
             .ok()
-            .or(file_cfg.callback_secret);
+            .map(|v| v == "true" || v == "1")
+            .or(file_cfg.serve_speedtest)
+            .unwrap_or(false);
+        if serve_speedtest && callback_secret.is_none() {
+            anyhow::bail!("开启 serve_speedtest 时必须设置 callback_secret，避免测速接口被他人用来消耗流量");
+        }
 
         let auto_submit = std::env::var("AUTO_SUBMIT")
             .ok()
@@ -131,36 +946,199 @@ impl Config {
             .or(file_cfg.auto_submit)
             .unwrap_or(false);
 
-        let telegram_bot_token = std::env::var("TELEGRAM_BOT_TOKEN")
-            .ok()
-            .or(file_cfg.telegram_bot_token);
+        let telegram_bot_token = secrets::resolve(
+            "telegram_bot_token",
+            &["TELEGRAM_BOT_TOKEN"],
+            &file_cfg.telegram_bot_token,
+            &file_cfg.telegram_bot_token_file,
+        )?;
 
         let telegram_chat_id = std::env::var("TELEGRAM_CHAT_ID")
             .ok()
             .and_then(|v| v.parse().ok())
             .or(file_cfg.telegram_chat_id);
 
+        let ticket_poll_interval = std::env::var("TICKET_POLL_INTERVAL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.ticket_poll_interval)
+            .unwrap_or(600);
+
+        let data_dir = std::env::var("DATA_DIR")
+            .ok()
+            .or(file_cfg.data_dir)
+            .unwrap_or_else(|| "data".to_string());
+
+        let followup_window_hours = std::env::var("FOLLOWUP_WINDOW_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.followup_window_hours)
+            .unwrap_or(24);
+
+        let recovery_checks = std::env::var("RECOVERY_CHECKS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.recovery_checks)
+            .unwrap_or(2);
+
+        let min_submit_interval_minutes = std::env::var("MIN_SUBMIT_INTERVAL_MINUTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.min_submit_interval_minutes)
+            .unwrap_or(180);
+
+        let max_tickets_per_day = std::env::var("MAX_TICKETS_PER_DAY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.max_tickets_per_day)
+            .unwrap_or(3);
+
+        let max_tickets_per_week = std::env::var("MAX_TICKETS_PER_WEEK")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.max_tickets_per_week);
+        if max_tickets_per_day == 0 || max_tickets_per_week == Some(0) {
+            anyhow::bail!(
+                "max_tickets_per_day / max_tickets_per_week 不能为 0；不想自动提交请关闭 auto_submit，不限制每周数量请不设置 max_tickets_per_week"
+            );
+        }
+
+        let confirm_samples = std::env::var("CONFIRM_SAMPLES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.confirm_samples)
+            .unwrap_or(2);
+
+        let confirm_interval_secs = std::env::var("CONFIRM_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.confirm_interval_secs)
+            .unwrap_or(30);
+
+        let confirm_quorum = std::env::var("CONFIRM_QUORUM")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.confirm_quorum);
+        // 总采样数为首次测速加 confirm_samples 次复测，quorum 超出范围要么永远成立要么永远不成立
+        if let Some(quorum) = confirm_quorum {
+            if quorum < 1 || quorum > confirm_samples + 1 {
+                anyhow::bail!(
+                    "confirm_quorum 必须在 1 到 confirm_samples + 1（{}）之间，当前为 {}",
+                    confirm_samples + 1,
+                    quorum
+                );
+            }
+        }
+
+        let congestion_confidence = std::env::var("CONGESTION_CONFIDENCE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.congestion_confidence)
+            .unwrap_or(0.6);
+
         Ok(Self {
-            access_key_id,
-            access_key_secret,
-            endpoint: "workorder.aliyuncs.com".to_string(),
+            credentials,
+            endpoint,
             api_version: "2021-06-10".to_string(),
+            api,
             product_id,
             category_id,
             ticket_title,
             ticket_description,
             cron_expression,
             speed_threshold,
+            speed_threshold_configured,
+            threshold_ratio,
+            instance_id,
+            region_id,
+            instance: None,
+            upload_threshold,
+            speedtest,
+            probe,
+            passive,
+            cms,
             feishu_webhook_url,
             callback_url,
             callback_port,
             callback_secret,
+            serve_speedtest,
             auto_submit,
             telegram_bot_token,
             telegram_chat_id,
+            ticket_poll_interval,
+            data_dir,
+            followup_window_hours,
+            recovery_checks,
+            min_submit_interval_minutes,
+            max_tickets_per_day,
+            max_tickets_per_week,
+            confirm_samples,
+            confirm_interval_secs,
+            confirm_quorum,
+            congestion_confidence,
         })
     }
 
+    /// 用 SWAS 查到的实例信息更新配置
+    ///
+    /// 未手动设置的阈值按套餐带宽的 `threshold_ratio` 推算；
+    /// 工单标题和描述仍是默认文案时，换成实例的地域和带宽。
+    pub fn apply_instance(&mut self, instance: InstanceInfo) {
+        let default_ctx = TicketContext::default();
+        let ctx = TicketContext {
+            region: instance.region_name.clone(),
+            bandwidth_mbps: instance.bandwidth_mbps,
+        };
+        if self.ticket_title == templates::default_title(&default_ctx) {
+            self.ticket_title = templates::default_title(&ctx);
+        }
+        if self.ticket_description == templates::default_description(&default_ctx) {
+            self.ticket_description = templates::default_description(&ctx);
+        }
+        if !self.speed_threshold_configured {
+            self.speed_threshold =
+                (instance.bandwidth_mbps as f64 * self.threshold_ratio * 10.0).round() / 10.0;
+            info!(
+                "按套餐带宽 {} Mbps 的 {:.0}% 设置测速阈值: {} Mbps",
+                instance.bandwidth_mbps,
+                self.threshold_ratio * 100.0,
+                self.speed_threshold
+            );
+        }
+        self.instance = Some(instance);
+    }
+
+    /// 工单文案使用的地域和带宽（未查询到实例时为默认的香港 30Mbps）
+    pub fn ticket_context(&self) -> TicketContext {
+        match &self.instance {
+            Some(i) => TicketContext {
+                region: i.region_name.clone(),
+                bandwidth_mbps: i.bandwidth_mbps,
+            },
+            None => TicketContext::default(),
+        }
+    }
+
+    /// 读取独立运行对端测速服务所需的监听端口和鉴权密钥（不要求阿里云凭证）
+    pub fn load_peer_server() -> Result<(u16, String)> {
+        let file_cfg = Self::load_file();
+        let port = std::env::var("CALLBACK_PORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .or(file_cfg.callback_port)
+            .unwrap_or(9876);
+        let secret = secrets::resolve(
+            "callback_secret",
+            &["CALLBACK_SECRET"],
+            &file_cfg.callback_secret,
+            &file_cfg.callback_secret_file,
+        )?;
+        let Some(secret) = secret else {
+            anyhow::bail!("对端测速服务必须设置 callback_secret，避免测速接口被他人用来消耗流量");
+        };
+        Ok((port, secret))
+    }
+
     fn load_file() -> FileConfig {
         match std::fs::read_to_string("config.json") {
             Ok(content) => match serde_json::from_str(&content) {
@@ -0,0 +1,818 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::config::{ApiConfig, AssumeRoleConfig, CredentialsConfig};
+use crate::rpc::AliyunRpcClient;
+
+/// STS API 版本
+const STS_VERSION: &str = "2015-04-01";
+/// 临时凭证在过期前多少秒刷新
+const REFRESH_BEFORE_SECS: i64 = 5 * 60;
+/// STS 默认接入地址
+pub const DEFAULT_STS_ENDPOINT: &str = "sts.aliyuncs.com";
+/// 默认的角色会话名称
+pub const DEFAULT_SESSION_NAME: &str = "aliyun-auto-ticket";
+/// 访问 ECS 元数据服务和凭证服务 URI 的超时时间
+const METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 一组阿里云访问凭证
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub access_key_secret: String,
+    /// STS 临时凭证的 SecurityToken，长期 AccessKey 为空
+    pub security_token: Option<String>,
+    /// 过期时间，长期 AccessKey 为空
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    pub fn new(access_key_id: String, access_key_secret: String) -> Self {
+        Self {
+            access_key_id,
+            access_key_secret,
+            security_token: None,
+            expiration: None,
+        }
+    }
+
+    /// 是否即将过期（需要刷新）
+    fn expires_soon(&self) -> bool {
+        self.expiration
+            .is_some_and(|t| (t - Utc::now()).num_seconds() < REFRESH_BEFORE_SECS)
+    }
+
+    /// 是否还没有过期（即将过期的也算）
+    fn usable(&self) -> bool {
+        self.expiration.is_none_or(|t| t > Utc::now())
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("access_key_secret", &"***")
+            .field(
+                "security_token",
+                &self.security_token.as_ref().map(|_| "***"),
+            )
+            .field("expiration", &self.expiration)
+            .finish()
+    }
+}
+
+/// 凭证的来源
+#[derive(Debug)]
+enum Source {
+    /// 配置中的长期 AccessKey 或 STS 临时凭证
+    Static(Credentials),
+    /// ECS 实例 RAM 角色，从元数据服务获取临时凭证；角色名为空时自动查询
+    EcsRamRole {
+        role_name: Option<String>,
+        metadata_endpoint: String,
+    },
+    /// 凭证服务 URI（ALIBABA_CLOUD_CREDENTIALS_URI），GET 返回临时凭证
+    Uri(String),
+    /// 用另一个来源的凭证调用 AssumeRole 获取临时凭证
+    AssumeRole {
+        base: Arc<CredentialProvider>,
+        role: AssumeRoleConfig,
+    },
+}
+
+/// 访问凭证提供者，所有阿里云 API 客户端共用
+///
+/// 临时凭证缓存到过期前 5 分钟，之后重新获取。
+#[derive(Debug)]
+pub struct CredentialProvider {
+    source: Source,
+    api: ApiConfig,
+    /// 缓存的凭证，只在读写时短暂加锁，网络请求期间不持有
+    cached: std::sync::Mutex<Option<Credentials>>,
+    /// 同一时间只有一个调用者去获取新凭证，避免并发请求同时打到 STS / 元数据服务
+    refreshing: Mutex<()>,
+}
+
+// ---- STS / 元数据服务响应结构 ----
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssumeRoleResponse {
+    credentials: StsCredentials,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StsCredentials {
+    access_key_id: String,
+    access_key_secret: String,
+    security_token: String,
+    expiration: String,
+}
+
+/// ECS 元数据服务和凭证服务 URI 返回的临时凭证
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TemporaryCredentials {
+    code: Option<String>,
+    access_key_id: String,
+    access_key_secret: String,
+    security_token: Option<String>,
+    expiration: Option<String>,
+}
+
+impl TemporaryCredentials {
+    fn into_credentials(self, source: &str) -> Result<Credentials> {
+        if let Some(code) = self.code.filter(|c| c != "Success") {
+            anyhow::bail!("{}返回的凭证无效 (Code: {})", source, code);
+        }
+        let expiration = match &self.expiration {
+            Some(e) => Some(parse_expiration(e)?),
+            None => None,
+        };
+        Ok(Credentials {
+            access_key_id: self.access_key_id,
+            access_key_secret: self.access_key_secret,
+            security_token: self.security_token.filter(|t| !t.is_empty()),
+            expiration,
+        })
+    }
+}
+
+/// aliyun CLI 配置文件（~/.aliyun/config.json）
+#[derive(Debug, Deserialize)]
+struct CliConfigFile {
+    current: Option<String>,
+    #[serde(default)]
+    profiles: Vec<CliProfile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CliProfile {
+    name: String,
+    mode: String,
+    access_key_id: String,
+    access_key_secret: String,
+    sts_token: String,
+    ram_role_name: String,
+    ram_role_arn: String,
+    ram_session_name: String,
+    expired_seconds: u64,
+}
+
+fn parse_expiration(value: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)
+        .with_context(|| format!("解析临时凭证过期时间 {} 失败", value))?
+        .with_timezone(&Utc))
+}
+
+impl CredentialProvider {
+    /// 按顺序查找凭证，使用第一个可用的来源：
+    ///
+    /// 1. 环境变量 / config.json 中的 AccessKey（可带 SecurityToken）
+    /// 2. aliyun CLI 配置文件中选定的 profile
+    /// 3. ECS 实例 RAM 角色（配置了角色名，或设为空字符串自动查询）
+    /// 4. 凭证服务 URI
+    ///
+    /// 配置了 `role` 时，再用找到的凭证扮演该角色。
+    pub fn from_chain(
+        settings: &CredentialsConfig,
+        role: Option<AssumeRoleConfig>,
+        api: ApiConfig,
+    ) -> Result<Self> {
+        let provider = match Self::find_source(settings, &api)? {
+            Some((source, provider)) => {
+                info!("阿里云凭证来源: {}", source);
+                provider
+            }
+            None => anyhow::bail!(
+                "未找到阿里云凭证，请在 config.json 或环境变量 ALIYUN_ACCESS_KEY_ID / ALIYUN_ACCESS_KEY_SECRET 中设置，\
+                 或配置 aliyun CLI（~/.aliyun/config.json）、ECS 实例 RAM 角色、ALIBABA_CLOUD_CREDENTIALS_URI"
+            ),
+        };
+        Ok(match role {
+            Some(role) => Self::assume_role(Arc::new(provider), role, api),
+            None => provider,
+        })
+    }
+
+    fn find_source(
+        settings: &CredentialsConfig,
+        api: &ApiConfig,
+    ) -> Result<Option<(String, Self)>> {
+        match (&settings.access_key_id, &settings.access_key_secret) {
+            (Some(id), Some(secret)) => {
+                let credentials = Credentials {
+                    security_token: settings.security_token.clone(),
+                    ..Credentials::new(id.clone(), secret.clone())
+                };
+                return Ok(Some((
+                    "环境变量或 config.json".to_string(),
+                    Self::fixed(credentials, api.clone()),
+                )));
+            }
+            (Some(_), None) => anyhow::bail!(
+                "缺少 access_key_secret，请在 config.json 或环境变量 ALIYUN_ACCESS_KEY_SECRET 中设置"
+            ),
+            (None, Some(_)) => anyhow::bail!(
+                "缺少 access_key_id，请在 config.json 或环境变量 ALIYUN_ACCESS_KEY_ID 中设置"
+            ),
+            (None, None) => {}
+        }
+
+        if let Some(found) = Self::from_cli_profile(settings, api)? {
+            return Ok(Some(found));
+        }
+
+        if let Some(role_name) = &settings.ecs_ram_role {
+            let source = Source::EcsRamRole {
+                role_name: Some(role_name.clone()).filter(|r| !r.is_empty()),
+                metadata_endpoint: settings.ecs_metadata_endpoint.clone(),
+            };
+            return Ok(Some((
+                "ECS 实例 RAM 角色".to_string(),
+                Self::new(source, api.clone()),
+            )));
+        }
+
+        if let Some(uri) = &settings.uri {
+            return Ok(Some((
+                format!("凭证服务 {}", uri),
+                Self::new(Source::Uri(uri.clone()), api.clone()),
+            )));
+        }
+
+        Ok(None)
+    }
+
+    /// 读取 aliyun CLI 配置文件中的 profile；文件不存在时返回 None
+    ///
+    /// 支持 AK、StsToken、RamRoleArn、EcsRamRole 四种模式。
+    fn from_cli_profile(
+        settings: &CredentialsConfig,
+        api: &ApiConfig,
+    ) -> Result<Option<(String, Self)>> {
+        let path = &settings.cli_config_file;
+        let text = match std::fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("读取 {} 失败", path)),
+        };
+        let file: CliConfigFile =
+            serde_json::from_str(&text).with_context(|| format!("解析 {} 失败", path))?;
+        let name = settings
+            .profile
+            .clone()
+            .or(file.current)
+            .unwrap_or_else(|| "default".to_string());
+        let Some(profile) = file.profiles.into_iter().find(|p| p.name == name) else {
+            if settings.profile.is_some() {
+                anyhow::bail!("{} 中没有名为 {} 的 profile", path, name);
+            }
+            warn!("{} 中没有 profile {}，跳过", path, name);
+            return Ok(None);
+        };
+
+        let access_key = || {
+            Credentials::new(
+                profile.access_key_id.clone(),
+                profile.access_key_secret.clone(),
+            )
+        };
+        let provider = match profile.mode.as_str() {
+            "AK" | "" => Self::fixed(access_key(), api.clone()),
+            "StsToken" => Self::fixed(
+                Credentials {
+                    security_token: Some(profile.sts_token.clone()).filter(|t| !t.is_empty()),
+                    ..access_key()
+                },
+                api.clone(),
+            ),
+            "RamRoleArn" => {
+                let role = AssumeRoleConfig {
+                    role_arn: profile.ram_role_arn.clone(),
+                    session_name: Some(profile.ram_session_name.clone())
+                        .filter(|s| !s.is_empty())
+                        .unwrap_or_else(|| DEFAULT_SESSION_NAME.to_string()),
+                    duration_secs: if profile.expired_seconds > 0 {
+                        profile.expired_seconds
+                    } else {
+                        3600
+                    },
+                    policy: None,
+                    endpoint: DEFAULT_STS_ENDPOINT.to_string(),
+                };
+                Self::assume_role(Arc::new(Self::fixed(access_key(), api.clone())), role, api.clone())
+            }
+            "EcsRamRole" => Self::new(
+                Source::EcsRamRole {
+                    role_name: Some(profile.ram_role_name.clone()).filter(|r| !r.is_empty()),
+                    metadata_endpoint: settings.ecs_metadata_endpoint.clone(),
+                },
+                api.clone(),
+            ),
+            other => anyhow::bail!(
+                "aliyun CLI profile {} 的模式 {} 暂不支持，请改用 AK、StsToken、RamRoleArn 或 EcsRamRole",
+                name,
+                other
+            ),
+        };
+        Ok(Some((
+            format!("aliyun CLI 配置 {}（profile {}）", path, name),
+            provider,
+        )))
+    }
+
+    fn new(source: Source, api: ApiConfig) -> Self {
+        Self {
+            source,
+            api,
+            cached: std::sync::Mutex::new(None),
+            refreshing: Mutex::new(()),
+        }
+    }
+
+    /// 固定凭证（长期 AccessKey，或带 SecurityToken 的临时凭证）
+    pub fn fixed(credentials: Credentials, api: ApiConfig) -> Self {
+        Self::new(Source::Static(credentials), api)
+    }
+
+    /// 用 `base` 提供的凭证扮演角色
+    pub fn assume_role(
+        base: Arc<CredentialProvider>,
+        role: AssumeRoleConfig,
+        api: ApiConfig,
+    ) -> Self {
+        Self::new(Source::AssumeRole { base, role }, api)
+    }
+
+    /// 当前可用的凭证，临时凭证即将过期时先刷新
+    ///
+    /// 已有别的调用者在刷新时，旧凭证只要还没真正过期就直接使用，不等待刷新完成。
+    pub async fn get(&self) -> Result<Credentials> {
+        if let Some(credentials) = self.cached(|c| !c.expires_soon()) {
+            return Ok(credentials);
+        }
+        let _guard = match self.refreshing.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                if let Some(credentials) = self.cached(Credentials::usable) {
+                    return Ok(credentials);
+                }
+                self.refreshing.lock().await
+            }
+        };
+        // 等待期间可能已经被别的调用者刷新
+        if let Some(credentials) = self.cached(|c| !c.expires_soon()) {
+            return Ok(credentials);
+        }
+
+        match self.fetch().await {
+            Ok(fresh) => {
+                *self.cached.lock().unwrap() = Some(fresh.clone());
+                Ok(fresh)
+            }
+            Err(e) => match self.cached(Credentials::usable) {
+                Some(credentials) => {
+                    warn!("刷新临时凭证失败，继续使用尚未过期的旧凭证: {:#}", e);
+                    Ok(credentials)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// 满足条件的缓存凭证
+    fn cached(&self, filter: impl Fn(&Credentials) -> bool) -> Option<Credentials> {
+        self.cached
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|c| filter(c))
+            .cloned()
+    }
+
+    /// 从凭证来源获取新的凭证（不经过缓存）
+    async fn fetch(&self) -> Result<Credentials> {
+        Ok(match &self.source {
+            Source::Static(credentials) => credentials.clone(),
+            Source::EcsRamRole {
+                role_name,
+                metadata_endpoint,
+            } => {
+                self.request_ecs_role(role_name.as_deref(), metadata_endpoint)
+                    .await?
+            }
+            Source::Uri(uri) => self.request_uri(uri).await?,
+            Source::AssumeRole { base, role } => self.request_role(base, role).await?,
+        })
+    }
+
+    /// 丢弃缓存的临时凭证（服务端提示 SecurityToken 过期时调用）
+    pub async fn invalidate(&self) {
+        if self.refreshable() {
+            *self.cached.lock().unwrap() = None;
+        }
+    }
+
+    /// 是否可以通过重新获取得到新的凭证
+    pub fn refreshable(&self) -> bool {
+        !matches!(self.source, Source::Static(_))
+    }
+
+    fn http(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(METADATA_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    }
+
+    /// 从 ECS 元数据服务获取实例 RAM 角色的临时凭证
+    ///
+    /// 优先使用加固模式（先取 metadata token），元数据服务不支持时退回普通模式。
+    async fn request_ecs_role(
+        &self,
+        role_name: Option<&str>,
+        endpoint: &str,
+    ) -> Result<Credentials> {
+        let http = self.http();
+        let base = endpoint.trim_end_matches('/');
+        let token = match http
+            .put(format!("{}/latest/api/token", base))
+            .header("X-aliyun-ecs-metadata-token-ttl-seconds", "21600")
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => resp.text().await.ok(),
+            _ => None,
+        };
+        let get = |path: String| {
+            let req = http.get(format!(
+                "{}/latest/meta-data/ram/security-credentials/{}",
+                base, path
+            ));
+            match &token {
+                Some(t) => req.header("X-aliyun-ecs-metadata-token", t),
+                None => req,
+            }
+        };
+
+        let role_name = match role_name {
+            Some(r) => r.to_string(),
+            None => get(String::new())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .context("查询 ECS 实例 RAM 角色失败，请确认实例已授予 RAM 角色")?
+                .text()
+                .await?
+                .lines()
+                .next()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .context("ECS 实例没有授予 RAM 角色")?,
+        };
+
+        let credentials: TemporaryCredentials = get(role_name.clone())
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("获取 ECS 实例 RAM 角色 {} 的凭证失败", role_name))?
+            .json()
+            .await
+            .context("解析 ECS 元数据服务返回的凭证失败")?;
+        let credentials = credentials.into_credentials("ECS 元数据服务")?;
+        info!("已获取 ECS 实例 RAM 角色 {} 的临时凭证", role_name);
+        Ok(credentials)
+    }
+
+    /// 从凭证服务 URI 获取临时凭证
+    async fn request_uri(&self, uri: &str) -> Result<Credentials> {
+        let credentials: TemporaryCredentials = self
+            .http()
+            .get(uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("请求凭证服务 {} 失败", uri))?
+            .json()
+            .await
+            .context("解析凭证服务返回的凭证失败")?;
+        credentials.into_credentials("凭证服务")
+    }
+
+    async fn request_role(
+        &self,
+        base: &Arc<CredentialProvider>,
+        role: &AssumeRoleConfig,
+    ) -> Result<Credentials> {
+        info!(
+            "正在通过 AssumeRole 获取角色 {} 的临时凭证...",
+            role.role_arn
+        );
+        let sts = AliyunRpcClient::with_credentials(
+            &self.api,
+            base.clone(),
+            role.endpoint.clone(),
+            STS_VERSION,
+        );
+
+        let mut params = std::collections::BTreeMap::new();
+        params.insert("RoleArn".to_string(), role.role_arn.clone());
+        params.insert("RoleSessionName".to_string(), role.session_name.clone());
+        params.insert(
+            "DurationSeconds".to_string(),
+            role.duration_secs.to_string(),
+        );
+        if let Some(policy) = &role.policy {
+            params.insert("Policy".to_string(), policy.clone());
+        }
+
+        // STS 客户端本身也通过 CredentialProvider 取凭证，需要装箱打断递归的 future 类型
+        let resp: AssumeRoleResponse = Box::pin(sts.post_form("AssumeRole", &params))
+            .await
+            .context("AssumeRole 获取临时凭证失败")?;
+        let c = resp.credentials;
+        let expiration = parse_expiration(&c.expiration)?;
+        info!(
+            "已获取临时凭证，有效期至 {}",
+            expiration
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        );
+        Ok(Credentials {
+            access_key_id: c.access_key_id,
+            access_key_secret: c.access_key_secret,
+            security_token: Some(c.security_token),
+            expiration: Some(expiration),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, put};
+    use axum::Router;
+
+    use super::*;
+    use crate::signer::SignatureAlgorithm;
+
+    fn api() -> ApiConfig {
+        ApiConfig {
+            scheme: "https".to_string(),
+            max_retries: 0,
+            timeout_secs: 5,
+            signature_algorithm: SignatureAlgorithm::HmacSha256,
+        }
+    }
+
+    fn settings() -> CredentialsConfig {
+        CredentialsConfig {
+            access_key_id: None,
+            access_key_secret: None,
+            security_token: None,
+            profile: None,
+            cli_config_file: "/nonexistent/aliyun/config.json".to_string(),
+            ecs_ram_role: None,
+            ecs_metadata_endpoint: "http://127.0.0.1:9".to_string(),
+            uri: None,
+        }
+    }
+
+    /// 凭证服务和元数据服务返回的 JSON，`valid_for` 为有效期
+    fn credentials_json(n: usize, valid_for: chrono::Duration) -> String {
+        serde_json::json!({
+            "Code": "Success",
+            "AccessKeyId": format!("STS.id{}", n),
+            "AccessKeySecret": format!("secret{}", n),
+            "SecurityToken": format!("token{}", n),
+            "Expiration": (Utc::now() + valid_for).to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        })
+        .to_string()
+    }
+
+    /// 测试桩的共享状态：请求次数和返回凭证的有效期
+    struct Stub {
+        hits: AtomicUsize,
+        valid_for: chrono::Duration,
+        /// 元数据服务是否支持加固模式
+        hardened: bool,
+    }
+
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{}", addr)
+    }
+
+    async fn credentials_uri(State(stub): State<Arc<Stub>>) -> String {
+        let n = stub.hits.fetch_add(1, Ordering::SeqCst) + 1;
+        credentials_json(n, stub.valid_for)
+    }
+
+    async fn metadata_token(State(stub): State<Arc<Stub>>) -> (StatusCode, &'static str) {
+        if stub.hardened {
+            (StatusCode::OK, "metadata-token")
+        } else {
+            (StatusCode::NOT_FOUND, "")
+        }
+    }
+
+    /// 加固模式下没有带 token 的请求返回 403
+    fn token_ok(stub: &Stub, headers: &HeaderMap) -> bool {
+        !stub.hardened
+            || headers
+                .get("X-aliyun-ecs-metadata-token")
+                .is_some_and(|v| v == "metadata-token")
+    }
+
+    async fn metadata_roles(
+        State(stub): State<Arc<Stub>>,
+        headers: HeaderMap,
+    ) -> (StatusCode, String) {
+        if !token_ok(&stub, &headers) {
+            return (StatusCode::FORBIDDEN, String::new());
+        }
+        (StatusCode::OK, "TicketRole\n".to_string())
+    }
+
+    async fn metadata_role(
+        State(stub): State<Arc<Stub>>,
+        headers: HeaderMap,
+        axum::extract::Path(role): axum::extract::Path<String>,
+    ) -> (StatusCode, String) {
+        if !token_ok(&stub, &headers) || role != "TicketRole" {
+            return (StatusCode::FORBIDDEN, String::new());
+        }
+        let n = stub.hits.fetch_add(1, Ordering::SeqCst) + 1;
+        (StatusCode::OK, credentials_json(n, stub.valid_for))
+    }
+
+    async fn start_stub(valid_for: chrono::Duration, hardened: bool) -> (String, Arc<Stub>) {
+        let stub = Arc::new(Stub {
+            hits: AtomicUsize::new(0),
+            valid_for,
+            hardened,
+        });
+        let router = Router::new()
+            .route("/credentials", get(credentials_uri))
+            .route("/latest/api/token", put(metadata_token))
+            .route(
+                "/latest/meta-data/ram/security-credentials/",
+                get(metadata_roles),
+            )
+            .route(
+                "/latest/meta-data/ram/security-credentials/{role}",
+                get(metadata_role),
+            )
+            .with_state(stub.clone());
+        (serve(router).await, stub)
+    }
+
+    #[tokio::test]
+    async fn uri_credentials_are_cached_until_close_to_expiry() {
+        let (base, stub) = start_stub(chrono::Duration::hours(1), false).await;
+        let provider = CredentialProvider::from_chain(
+            &CredentialsConfig {
+                uri: Some(format!("{}/credentials", base)),
+                ..settings()
+            },
+            None,
+            api(),
+        )
+        .unwrap();
+
+        let first = provider.get().await.unwrap();
+        assert_eq!(first.access_key_id, "STS.id1");
+        assert_eq!(first.security_token.as_deref(), Some("token1"));
+        assert!(first.expiration.is_some());
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.id1");
+        assert_eq!(stub.hits.load(Ordering::SeqCst), 1);
+
+        // 服务端提示凭证失效后重新获取
+        assert!(provider.refreshable());
+        provider.invalidate().await;
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.id2");
+    }
+
+    #[tokio::test]
+    async fn credentials_expiring_soon_are_refreshed() {
+        let (base, stub) = start_stub(chrono::Duration::minutes(2), false).await;
+        let provider = CredentialProvider::new(Source::Uri(format!("{}/credentials", base)), api());
+
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.id1");
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.id2");
+        assert_eq!(stub.hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn refresh_failure_falls_back_to_unexpired_credentials() {
+        let provider = CredentialProvider::new(
+            Source::Uri("http://127.0.0.1:9/credentials".to_string()),
+            api(),
+        );
+        let old = Credentials {
+            expiration: Some(Utc::now() + chrono::Duration::minutes(2)),
+            ..Credentials::new("STS.old".to_string(), "secret".to_string())
+        };
+        *provider.cached.lock().unwrap() = Some(old);
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.old");
+
+        // 已经过期的凭证不再使用
+        *provider.cached.lock().unwrap() = Some(Credentials {
+            expiration: Some(Utc::now() - chrono::Duration::minutes(1)),
+            ..Credentials::new("STS.old".to_string(), "secret".to_string())
+        });
+        assert!(provider.get().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ecs_role_is_discovered_with_hardened_metadata() {
+        for hardened in [true, false] {
+            let (base, stub) = start_stub(chrono::Duration::hours(6), hardened).await;
+            let provider = CredentialProvider::from_chain(
+                &CredentialsConfig {
+                    ecs_ram_role: Some(String::new()),
+                    ecs_metadata_endpoint: base,
+                    ..settings()
+                },
+                None,
+                api(),
+            )
+            .unwrap();
+
+            let credentials = provider.get().await.unwrap();
+            assert_eq!(credentials.access_key_id, "STS.id1");
+            assert_eq!(credentials.access_key_secret, "secret1");
+            assert_eq!(stub.hits.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    /// 把 aliyun CLI 配置写到临时文件，返回路径
+    fn cli_config(content: serde_json::Value) -> String {
+        let path = std::env::temp_dir().join(format!("aliyun-cli-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn cli_profiles() {
+        let (base, stub) = start_stub(chrono::Duration::hours(6), true).await;
+        let path = cli_config(serde_json::json!({
+            "current": "ak",
+            "profiles": [
+                { "name": "ak", "mode": "AK", "access_key_id": "LTAIak", "access_key_secret": "ak-secret" },
+                {
+                    "name": "sts", "mode": "StsToken",
+                    "access_key_id": "STS.cli", "access_key_secret": "sts-secret", "sts_token": "cli-token"
+                },
+                { "name": "ecs", "mode": "EcsRamRole", "ram_role_name": "TicketRole" },
+                { "name": "sso", "mode": "CloudSSO" }
+            ]
+        }));
+        let with_profile = |profile: Option<&str>| CredentialsConfig {
+            profile: profile.map(str::to_string),
+            cli_config_file: path.clone(),
+            ecs_metadata_endpoint: base.clone(),
+            ..settings()
+        };
+
+        // 未指定 profile 时使用 current
+        let provider = CredentialProvider::from_chain(&with_profile(None), None, api()).unwrap();
+        let credentials = provider.get().await.unwrap();
+        assert_eq!(credentials.access_key_id, "LTAIak");
+        assert!(credentials.security_token.is_none());
+        assert!(!provider.refreshable());
+
+        let provider =
+            CredentialProvider::from_chain(&with_profile(Some("sts")), None, api()).unwrap();
+        let credentials = provider.get().await.unwrap();
+        assert_eq!(credentials.security_token.as_deref(), Some("cli-token"));
+
+        let provider =
+            CredentialProvider::from_chain(&with_profile(Some("ecs")), None, api()).unwrap();
+        assert_eq!(provider.get().await.unwrap().access_key_id, "STS.id1");
+        assert_eq!(stub.hits.load(Ordering::SeqCst), 1);
+
+        let err =
+            CredentialProvider::from_chain(&with_profile(Some("sso")), None, api()).unwrap_err();
+        assert!(format!("{:#}", err).contains("暂不支持"));
+        let err = CredentialProvider::from_chain(&with_profile(Some("missing")), None, api())
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("没有名为 missing"));
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
 use tracing::{error, info};
 
+use crate::speedtest::Verdict;
+
 /// 发送飞书文本消息
 pub async fn send_text(webhook_url: &str, text: &str) -> Result<()> {
     let body = serde_json::json!({
@@ -28,7 +30,7 @@ pub async fn send_text(webhook_url: &str, text: &str) -> Result<()> {
 /// 发送带"提交工单"按钮的飞书交互卡片
 pub async fn send_throttle_card(
     webhook_url: &str,
-    speed_mbps: f64,
+    verdict: &Verdict,
     threshold: f64,
     approve_url: &str,
 ) -> Result<()> {
@@ -45,8 +47,11 @@ pub async fn send_throttle_card(
                     "text": {
                         "tag": "lark_md",
                         "content": format!(
-                            "**下载速度**: {:.2} Mbps\n**阈值**: {} Mbps\n**状态**: 低于阈值，疑似被限速",
-                            speed_mbps, threshold
+                            "**下载速度**: {:.2} Mbps\n**阈值**: {} Mbps\n**状态**: {}，疑似被限速{}",
+                            verdict.mbps,
+                            threshold,
+                            verdict.alert_reasons(),
+                            verdict.detail()
                         )
                     }
                 },
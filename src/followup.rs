@@ -0,0 +1,160 @@
+use tracing::{error, info, warn};
+
+use crate::client::{self, WorkorderClient};
+use crate::config::Config;
+use crate::tracker::TicketTracker;
+use crate::{history, notify, templates};
+
+/// 检测到限速时，优先复用已有工单，避免重复提交
+///
+/// - 已有处理中的工单：只通知，不重复提交
+/// - 工单待我方反馈，或最近被标记为"已处理"但仍然限速：在原工单上追加回复
+///
+/// 返回 `Some(说明文字)` 表示已由已有工单处理，调用方不应再提交新工单。
+pub async fn handle_existing_ticket(
+    config: &Config,
+    tracker: &TicketTracker,
+    speed: f64,
+) -> Option<String> {
+    let client = WorkorderClient::new(config.clone());
+    let open = match client.find_open_ticket(&tracker.tracked_ids().await).await {
+        Ok(t) => t,
+        Err(e) => {
+            warn!("查询已有工单失败，继续提交流程: {:#}", e);
+            None
+        }
+    };
+
+    let (ticket_id, pending) = match open {
+        Some(ticket) => {
+            let ticket_id = ticket.id.clone().unwrap_or_default();
+            tracker.track(&ticket_id).await;
+            if ticket.status() != "pending" {
+                info!("已有未完结工单 {}，跳过提交", ticket_id);
+                return Some(format!(
+                    "已有处理中的工单 {}（{}），本次不再重复提交",
+                    ticket_id,
+                    client::status_label(ticket.status())
+                ));
+            }
+            (ticket_id, true)
+        }
+        None => {
+            let window = chrono::Duration::hours(config.followup_window_hours as i64);
+            (history::recently_resolved_ticket(&config.data_dir, window)?, false)
+        }
+    };
+
+    let previous = history::speed_before_ticket(&config.data_dir, &ticket_id);
+    let content = templates::random_followup(&config.ticket_context(), &ticket_id, previous, speed);
+    match client.reply_ticket(&ticket_id, &content).await {
+        Ok(()) => {
+            history::record(
+                &config.data_dir,
+                history::Event::Reply {
+                    ticket_id: ticket_id.clone(),
+                    mbps: speed,
+                    error: None,
+                },
+            );
+            tracker.track(&ticket_id).await;
+            Some(format!("限速仍未解除，已在工单 {} 上追加回复", ticket_id))
+        }
+        Err(e) => {
+            error!("回复工单 {} 失败: {:#}", ticket_id, e);
+            history::record(
+                &config.data_dir,
+                history::Event::Reply {
+                    ticket_id: ticket_id.clone(),
+                    mbps: speed,
+                    error: Some(format!("{:#}", e)),
+                },
+            );
+            // 待反馈的工单仍在处理中，不再另开；已处理的工单回复失败则改为提交新工单
+            pending.then(|| format!("❌ 在工单 {} 上追加回复失败: {:#}", ticket_id, e))
+        }
+    }
+}
+
+/// 测速恢复正常后，关闭连续 `recovery_checks` 次测速都正常的工单，并推送恢复摘要
+pub async fn close_recovered_tickets(config: &Config, tracker: &TicketTracker, speed: f64) {
+    if config.recovery_checks == 0 {
+        return;
+    }
+
+    let client = WorkorderClient::new(config.clone());
+    for (ticket_id, submitted_at) in history::unclosed_tickets(&config.data_dir) {
+        let (streak, recovered_at) =
+            history::normal_streak(
+                &config.data_dir,
+                config.speed_threshold,
+                config.upload_threshold,
+                submitted_at,
+            );
+        if streak < config.recovery_checks {
+            continue;
+        }
+
+        // 已在控制台手动关闭的工单只补记状态，不再调用关闭接口
+        let already_closed = match client.get_ticket(&ticket_id).await {
+            Ok(t) => matches!(t.status(), "closed" | "evaluated"),
+            Err(e) => {
+                warn!("查询工单 {} 状态失败: {:#}", ticket_id, e);
+                false
+            }
+        };
+        if !already_closed {
+            if let Err(e) = client.close_ticket(&ticket_id).await {
+                error!("关闭工单 {} 失败: {:#}", ticket_id, e);
+                history::record(
+                    &config.data_dir,
+                    history::Event::CloseFailed {
+                        ticket_id: ticket_id.clone(),
+                        error: format!("{:#}", e),
+                    },
+                );
+                // 多次失败后不再自动重试，提醒手动关闭
+                let failures = history::close_failures(&config.data_dir, &ticket_id);
+                if failures >= history::MAX_CLOSE_FAILURES {
+                    let msg = format!(
+                        "⚠️ 带宽已恢复，但自动关闭工单 {} 连续失败 {} 次，不再重试，请到阿里云控制台手动关闭\n最近一次错误: {:#}",
+                        ticket_id,
+                        history::MAX_CLOSE_FAILURES,
+                        e
+                    );
+                    notify::broadcast(config, &msg).await;
+                }
+                continue;
+            }
+        }
+        history::record(
+            &config.data_dir,
+            history::Event::TicketStatus {
+                ticket_id: ticket_id.clone(),
+                status: "closed".to_string(),
+            },
+        );
+        tracker.untrack(&ticket_id).await;
+        if already_closed {
+            info!("工单 {} 已被手动关闭", ticket_id);
+            continue;
+        }
+
+        let recovery = recovered_at.unwrap_or_else(chrono::Local::now) - submitted_at;
+        let before = match history::speed_before_ticket(&config.data_dir, &ticket_id) {
+            Some(b) => format!("{:.2} Mbps", b),
+            None => "未知".to_string(),
+        };
+        let msg = format!(
+            "✅ 带宽已恢复，已自动关闭工单 {}\n限速时: {} → 当前: {:.2} Mbps\n提交到恢复用时: {}h {}m（连续 {} 次测速正常）",
+            ticket_id,
+            before,
+            speed,
+            recovery.num_hours(),
+            recovery.num_minutes() % 60,
+            streak
+        );
+        info!("工单 {} 已因带宽恢复自动关闭", ticket_id);
+        notify::broadcast(config, &msg).await;
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+
+use crate::config::Config;
+use crate::history;
+
+/// 检查工单提交频率限制
+///
+/// 依据历史记录中成功提交的工单计算，重启后依然有效：
+/// 最小提交间隔、滚动 24 小时内最大数量、可选的滚动 7 天内最大数量。
+/// 被限制时返回的错误中包含原因和下次允许提交的时间。
+pub fn check(config: &Config) -> Result<()> {
+    let now = Local::now();
+    let times = history::ticket_times(&config.data_dir);
+    let mut blocks: Vec<(String, DateTime<Local>)> = Vec::new();
+
+    if let Some(last) = times.last() {
+        let interval = Duration::minutes(config.min_submit_interval_minutes as i64);
+        if *last + interval > now {
+            blocks.push((
+                format!("距上次提交不足 {} 分钟", config.min_submit_interval_minutes),
+                *last + interval,
+            ));
+        }
+    }
+
+    if let Some(block) = window_block(&times, now, Duration::hours(24), config.max_tickets_per_day) {
+        blocks.push((format!("24 小时内已提交 {} 个工单", config.max_tickets_per_day), block));
+    }
+
+    if let Some(max) = config.max_tickets_per_week {
+        if let Some(block) = window_block(&times, now, Duration::days(7), max) {
+            blocks.push((format!("7 天内已提交 {} 个工单", max), block));
+        }
+    }
+
+    if let Some((reason, next)) = blocks.into_iter().max_by_key(|(_, t)| *t) {
+        anyhow::bail!(
+            "提交频率受限（{}），下次可提交时间: {}",
+            reason,
+            next.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+/// 滚动窗口内提交数达到上限时，返回窗口内最早一次提交滑出窗口的时间
+fn window_block(
+    times: &[DateTime<Local>],
+    now: DateTime<Local>,
+    window: Duration,
+    max: u32,
+) -> Option<DateTime<Local>> {
+    let in_window: Vec<&DateTime<Local>> = times.iter().filter(|t| **t + window > now).collect();
+    if (in_window.len() as u32) < max {
+        return None;
+    }
+    // 需要滑出窗口的数量 = 当前数量 - max + 1，按时间顺序取对应那一次
+    // （max 为 0 时配置加载会拒绝，这里用 get 避免越界）
+    let idx = in_window.len() - max as usize;
+    in_window.get(idx).map(|t| **t + window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_block_releases_when_enough_tickets_slide_out() {
+        let now = Local::now();
+        let day = Duration::hours(24);
+        // (说明, 提交时间是几小时前, 上限, 期望几小时后可再提交)
+        let cases: [(&str, &[i64], u32, Option<i64>); 7] = [
+            ("没有提交", &[], 3, None),
+            ("未达上限", &[10, 1], 3, None),
+            ("刚好达到上限", &[20, 10, 1], 3, Some(4)),
+            ("超过上限时等更多工单滑出", &[22, 20, 10, 1], 3, Some(4)),
+            ("窗口外的不算", &[30, 10, 1], 3, None),
+            ("上限为 1", &[1], 1, Some(23)),
+            ("上限为 0 不越界", &[10, 1], 0, None),
+        ];
+        for (name, hours_ago, max, expected) in cases {
+            let times: Vec<DateTime<Local>> =
+                hours_ago.iter().map(|h| now - Duration::hours(*h)).collect();
+            assert_eq!(
+                window_block(&times, now, day, max),
+                expected.map(|h| now + Duration::hours(h)),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,800 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::client::{self, SubmittedTicket};
+use crate::probe::ProbeReport;
+use crate::speedtest::SpeedSample;
+
+/// 历史记录文件名（位于 data_dir 下，每行一条 JSON）
+const HISTORY_FILE: &str = "history.jsonl";
+/// 自动关闭工单失败这么多次后不再尝试，交给用户手动处理
+pub const MAX_CLOSE_FAILURES: usize = 3;
+
+/// 一条历史记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub time: chrono::DateTime<chrono::Local>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// 历史事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// 一次测速结果（有复测时为所有采样的中位数）
+    SpeedTest {
+        mbps: f64,
+        bytes: u64,
+        duration_secs: f64,
+        /// 多线程测速时每个连接的速度
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        per_stream: Vec<f64>,
+        /// 有复测时每次采样的速度
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        samples: Vec<f64>,
+    },
+    /// 一次上传测速结果（有复测时为所有采样的中位数）
+    UploadTest {
+        mbps: f64,
+        bytes: u64,
+        duration_secs: f64,
+        /// 有复测时每次采样的速度
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        samples: Vec<f64>,
+    },
+    /// 一次延迟探测结果
+    Probe {
+        avg_ms: f64,
+        p95_ms: f64,
+        jitter_ms: f64,
+        loss_ratio: f64,
+        /// 是否超过配置的阈值
+        exceeded: bool,
+    },
+    /// 一次提交工单的结果（成功时有 ticket_id，失败时有 error）
+    Ticket {
+        source: String,
+        ticket_id: Option<String>,
+        request_id: Option<String>,
+        error: Option<String>,
+    },
+    /// 审批通过或取消
+    Decision { source: String, approved: bool },
+    /// 跟踪到的工单状态变化
+    TicketStatus { ticket_id: String, status: String },
+    /// 在已有工单上追加的回复
+    Reply {
+        ticket_id: String,
+        mbps: f64,
+        error: Option<String>,
+    },
+    /// 自动关闭工单失败
+    CloseFailed { ticket_id: String, error: String },
+}
+
+impl Record {
+    /// 单行文字描述，用于 Telegram / CLI / 网页展示
+    pub fn summary(&self) -> String {
+        let time = self.time.format("%m-%d %H:%M");
+        match &self.event {
+            Event::SpeedTest {
+                mbps,
+                bytes,
+                duration_secs,
+                per_stream,
+                samples,
+            } => format!(
+                "{} 测速 {:.2} Mbps（{:.1} MB / {:.1}s{}）{}",
+                time,
+                mbps,
+                *bytes as f64 / 1_000_000.0,
+                duration_secs,
+                if per_stream.len() > 1 {
+                    format!(" / {} 路", per_stream.len())
+                } else {
+                    String::new()
+                },
+                samples_note(samples)
+            ),
+            Event::UploadTest {
+                mbps,
+                bytes,
+                duration_secs,
+                samples,
+            } => format!(
+                "{} 上传测速 {:.2} Mbps（{:.1} MB / {:.1}s）{}",
+                time,
+                mbps,
+                *bytes as f64 / 1_000_000.0,
+                duration_secs,
+                samples_note(samples)
+            ),
+            Event::Probe {
+                avg_ms,
+                p95_ms,
+                jitter_ms,
+                loss_ratio,
+                exceeded,
+            } => format!(
+                "{} 延迟 平均 {:.0} / P95 {:.0} ms，抖动 {:.0} ms，失败 {:.0}%{}",
+                time,
+                avg_ms,
+                p95_ms,
+                jitter_ms,
+                loss_ratio * 100.0,
+                if *exceeded { " ⚠️" } else { "" }
+            ),
+            Event::Ticket {
+                source,
+                ticket_id: Some(id),
+                ..
+            } => format!("{} [{}] 提交工单 {}", time, source, id),
+            Event::Ticket { source, error, .. } => format!(
+                "{} [{}] 提交工单失败: {}",
+                time,
+                source,
+                error.as_deref().unwrap_or("未知错误")
+            ),
+            Event::Decision { source, approved } => format!(
+                "{} [{}] {}",
+                time,
+                source,
+                if *approved { "审批通过" } else { "取消提交" }
+            ),
+            Event::TicketStatus { ticket_id, status } => format!(
+                "{} 工单 {} → {}",
+                time,
+                ticket_id,
+                client::status_label(status)
+            ),
+            Event::Reply {
+                ticket_id,
+                mbps,
+                error: None,
+            } => format!("{} 回复工单 {}（实测 {:.2} Mbps）", time, ticket_id, mbps),
+            Event::Reply {
+                ticket_id,
+                error: Some(e),
+                ..
+            } => format!("{} 回复工单 {} 失败: {}", time, ticket_id, e),
+            Event::CloseFailed { ticket_id, error } => {
+                format!("{} 关闭工单 {} 失败: {}", time, ticket_id, error)
+            }
+        }
+    }
+}
+
+/// 复测时各次采样的速度，如" [12.3 / 11.8 / 12.1]"
+fn samples_note(samples: &[f64]) -> String {
+    if samples.len() <= 1 {
+        return String::new();
+    }
+    let speeds: Vec<String> = samples.iter().map(|s| format!("{:.1}", s)).collect();
+    format!(" [{}]", speeds.join(" / "))
+}
+
+fn history_path(data_dir: &str) -> PathBuf {
+    PathBuf::from(data_dir).join(HISTORY_FILE)
+}
+
+fn append(data_dir: &str, event: Event) -> Result<()> {
+    std::fs::create_dir_all(data_dir).with_context(|| format!("创建数据目录 {} 失败", data_dir))?;
+    let record = Record {
+        time: chrono::Local::now(),
+        event,
+    };
+    let mut line = serde_json::to_string(&record)?;
+    line.push('\n');
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(history_path(data_dir))
+        .context("打开历史记录文件失败")?;
+    file.write_all(line.as_bytes())
+        .context("写入历史记录失败")?;
+    Ok(())
+}
+
+/// 追加一条历史记录（写入失败只打日志，不影响主流程）
+pub fn record(data_dir: &str, event: Event) {
+    if let Err(e) = append(data_dir, event) {
+        warn!("历史记录写入失败: {:#}", e);
+    }
+}
+
+/// 记录一次测速结果
+pub fn record_speed(data_dir: &str, sample: &SpeedSample) {
+    record_speed_check(data_dir, std::slice::from_ref(sample), sample.mbps);
+}
+
+/// 记录一次检测的下载结果：不论复测几次都只写一条，速度为判定用的中位数 `mbps`
+///
+/// 恢复检测按记录条数计数，每次检测只写一条才不会把一次检测的多次复测算成多次正常。
+pub fn record_speed_check(data_dir: &str, samples: &[SpeedSample], mbps: f64) {
+    let Some(first) = samples.first() else {
+        return;
+    };
+    record(
+        data_dir,
+        Event::SpeedTest {
+            mbps,
+            bytes: samples.iter().map(|s| s.bytes).sum(),
+            duration_secs: samples.iter().map(|s| s.duration_secs).sum(),
+            per_stream: if first.per_stream.len() > 1 {
+                first.per_stream.clone()
+            } else {
+                Vec::new()
+            },
+            samples: sample_speeds(samples),
+        },
+    );
+}
+
+/// 记录一次上传测速结果
+pub fn record_upload(data_dir: &str, sample: &SpeedSample) {
+    record_upload_check(data_dir, std::slice::from_ref(sample), sample.mbps);
+}
+
+/// 记录一次检测的上传结果，规则同 [`record_speed_check`]
+pub fn record_upload_check(data_dir: &str, samples: &[SpeedSample], mbps: f64) {
+    if samples.is_empty() {
+        return;
+    }
+    record(
+        data_dir,
+        Event::UploadTest {
+            mbps,
+            bytes: samples.iter().map(|s| s.bytes).sum(),
+            duration_secs: samples.iter().map(|s| s.duration_secs).sum(),
+            samples: sample_speeds(samples),
+        },
+    );
+}
+
+/// 多次采样时各次的速度，单次采样时为空
+fn sample_speeds(samples: &[SpeedSample]) -> Vec<f64> {
+    if samples.len() > 1 {
+        samples.iter().map(|s| s.mbps).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// 记录一次延迟探测结果
+pub fn record_probe(data_dir: &str, report: &ProbeReport) {
+    if let Some(s) = report.primary() {
+        record(
+            data_dir,
+            Event::Probe {
+                avg_ms: s.avg_ms,
+                p95_ms: s.p95_ms,
+                jitter_ms: s.jitter_ms,
+                loss_ratio: s.loss_ratio,
+                exceeded: report.exceeded(),
+            },
+        );
+    }
+}
+
+/// 记录一次提交工单的结果
+pub fn record_ticket(data_dir: &str, source: &str, result: &Result<SubmittedTicket>) {
+    let event = match result {
+        Ok(t) => Event::Ticket {
+            source: source.to_string(),
+            ticket_id: Some(t.ticket_id.clone()),
+            request_id: t.request_id.clone(),
+            error: None,
+        },
+        Err(e) => Event::Ticket {
+            source: source.to_string(),
+            ticket_id: None,
+            request_id: None,
+            error: Some(format!("{:#}", e)),
+        },
+    };
+    record(data_dir, event);
+}
+
+/// 记录一次审批 / 取消操作
+pub fn record_decision(data_dir: &str, source: &str, approved: bool) {
+    record(
+        data_dir,
+        Event::Decision {
+            source: source.to_string(),
+            approved,
+        },
+    );
+}
+
+/// 读取全部历史记录（按时间顺序，损坏的行会被跳过）
+pub fn load(data_dir: &str) -> Vec<Record> {
+    let content = match std::fs::read_to_string(history_path(data_dir)) {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+    content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
+
+/// 最近 n 条记录（最新的在前）
+pub fn recent(data_dir: &str, n: usize) -> Vec<Record> {
+    let mut records = load(data_dir);
+    records.reverse();
+    records.truncate(n);
+    records
+}
+
+/// 最近一次测速记录：(时间, 速度 Mbps)
+pub fn last_speed(data_dir: &str) -> Option<(chrono::DateTime<chrono::Local>, f64)> {
+    load(data_dir).into_iter().rev().find_map(|r| match r.event {
+        Event::SpeedTest { mbps, .. } => Some((r.time, mbps)),
+        _ => None,
+    })
+}
+
+/// 最近一次延迟探测记录
+pub fn last_probe(data_dir: &str) -> Option<Record> {
+    load(data_dir)
+        .into_iter()
+        .rev()
+        .find(|r| matches!(r.event, Event::Probe { .. }))
+}
+
+/// 已提交但尚未完结的工单：工单号 -> 最近一次记录的状态
+pub fn open_tickets(data_dir: &str) -> Vec<(String, String)> {
+    let mut open: Vec<(String, String)> = Vec::new();
+    for r in load(data_dir) {
+        match r.event {
+            Event::Ticket {
+                ticket_id: Some(id),
+                ..
+            } if !open.iter().any(|(k, _)| *k == id) => {
+                open.push((id, "dealing".to_string()));
+            }
+            Event::TicketStatus { ticket_id, status } => {
+                if client::is_finished(&status) {
+                    open.retain(|(k, _)| *k != ticket_id);
+                } else if let Some(entry) = open.iter_mut().find(|(k, _)| *k == ticket_id) {
+                    entry.1 = status;
+                } else {
+                    open.push((ticket_id, status));
+                }
+            }
+            _ => {}
+        }
+    }
+    open
+}
+
+/// 提交某个工单前最近一次的测速结果（Mbps）
+pub fn speed_before_ticket(data_dir: &str, ticket_id: &str) -> Option<f64> {
+    let mut last_speed = None;
+    for r in load(data_dir) {
+        match r.event {
+            Event::SpeedTest { mbps, .. } => last_speed = Some(mbps),
+            Event::Ticket {
+                ticket_id: Some(id),
+                ..
+            } if id == ticket_id => return last_speed,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 最近 `within` 时间内被阿里云标记为"已处理"、且之后没有再变化的工单
+pub fn recently_resolved_ticket(data_dir: &str, within: chrono::Duration) -> Option<String> {
+    let since = chrono::Local::now() - within;
+    let mut latest: Option<(String, String, chrono::DateTime<chrono::Local>)> = None;
+    for r in load(data_dir) {
+        if let Event::TicketStatus { ticket_id, status } = r.event {
+            latest = Some((ticket_id, status, r.time));
+        }
+    }
+    match latest {
+        Some((ticket_id, status, time)) if status == "completed" && time >= since => Some(ticket_id),
+        _ => None,
+    }
+}
+
+/// 本工具提交、尚未关闭的工单（包括已处理待确认的）：(工单号, 提交时间)
+///
+/// 自动关闭已失败 [`MAX_CLOSE_FAILURES`] 次的工单不再列出，避免每次都重试、
+/// 被动监测模式下也不会因为它一直触发定时测速。
+pub fn unclosed_tickets(data_dir: &str) -> Vec<(String, chrono::DateTime<chrono::Local>)> {
+    let mut unclosed: Vec<(String, chrono::DateTime<chrono::Local>, usize)> = Vec::new();
+    for r in load(data_dir) {
+        match r.event {
+            Event::Ticket {
+                ticket_id: Some(id),
+                ..
+            } => unclosed.push((id, r.time, 0)),
+            Event::TicketStatus { ticket_id, status } if status == "closed" || status == "evaluated" => {
+                unclosed.retain(|(k, _, _)| *k != ticket_id);
+            }
+            Event::CloseFailed { ticket_id, .. } => {
+                if let Some(entry) = unclosed.iter_mut().find(|(k, _, _)| *k == ticket_id) {
+                    entry.2 += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    unclosed
+        .into_iter()
+        .filter(|(_, _, failures)| *failures < MAX_CLOSE_FAILURES)
+        .map(|(id, time, _)| (id, time))
+        .collect()
+}
+
+/// 某个工单自动关闭失败的次数
+pub fn close_failures(data_dir: &str, ticket_id: &str) -> usize {
+    load(data_dir)
+        .iter()
+        .filter(|r| matches!(&r.event, Event::CloseFailed { ticket_id: id, .. } if id == ticket_id))
+        .count()
+}
+
+/// `since` 之后末尾连续不低于阈值的检测：(次数, 其中第一次的时间)
+///
+/// 每次检测（含复测）只有一条下载记录，按检测次数统计；设置了 `upload_threshold` 时，中间出现低于它的上传测速也会打断连续，
+/// 超过阈值的延迟探测同样会打断连续。
+pub fn normal_streak(
+    data_dir: &str,
+    threshold: f64,
+    upload_threshold: Option<f64>,
+    since: chrono::DateTime<chrono::Local>,
+) -> (u32, Option<chrono::DateTime<chrono::Local>>) {
+    let mut count = 0;
+    let mut first = None;
+    for r in load(data_dir).into_iter().rev() {
+        if r.time < since {
+            break;
+        }
+        match r.event {
+            Event::SpeedTest { mbps, .. } => {
+                if mbps < threshold {
+                    break;
+                }
+                count += 1;
+                first = Some(r.time);
+            }
+            Event::UploadTest { mbps, .. } if upload_threshold.is_some_and(|t| mbps < t) => break,
+            Event::Probe { exceeded: true, .. } => break,
+            _ => {}
+        }
+    }
+    (count, first)
+}
+
+/// 所有成功提交工单的时间（按时间顺序）
+pub fn ticket_times(data_dir: &str) -> Vec<chrono::DateTime<chrono::Local>> {
+    load(data_dir)
+        .into_iter()
+        .filter_map(|r| match r.event {
+            Event::Ticket {
+                ticket_id: Some(_),
+                ..
+            } => Some(r.time),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个测试使用独立的临时数据目录
+    fn temp_dir() -> String {
+        std::env::temp_dir()
+            .join(format!("aliyun-auto-ticket-history-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn sample(mbps: f64) -> SpeedSample {
+        SpeedSample {
+            mbps,
+            bytes: (mbps * 1_000_000.0 / 8.0) as u64,
+            duration_secs: 1.0,
+            per_stream: vec![mbps],
+            series: Vec::new(),
+        }
+    }
+
+    fn history_check(dir: &str, speeds: &[f64], median: f64) {
+        let samples: Vec<SpeedSample> = speeds.iter().map(|s| sample(*s)).collect();
+        record_speed_check(dir, &samples, median);
+    }
+
+    #[test]
+    fn retakes_count_as_one_check() {
+        let dir = temp_dir();
+        let since = chrono::Local::now() - chrono::Duration::minutes(1);
+
+        // 一次检测的三次采样中位数高于阈值，也只算一次正常
+        history_check(&dir, &[5.0, 25.0, 25.0], 25.0);
+        assert_eq!(normal_streak(&dir, 20.0, None, since).0, 1);
+        let records = load(&dir);
+        assert_eq!(records.len(), 1);
+        assert!(records[0].summary().contains("[5.0 / 25.0 / 25.0]"));
+
+        history_check(&dir, &[30.0], 30.0);
+        assert_eq!(normal_streak(&dir, 20.0, None, since).0, 2);
+    }
+
+    fn submitted(dir: &str, id: &str) {
+        record(
+            dir,
+            Event::Ticket {
+                source: "test".to_string(),
+                ticket_id: Some(id.to_string()),
+                request_id: None,
+                error: None,
+            },
+        );
+    }
+
+    #[test]
+    fn unclosed_tickets_give_up_after_repeated_close_failures() {
+        let dir = temp_dir();
+        submitted(&dir, "T1");
+        submitted(&dir, "T2");
+        for _ in 0..MAX_CLOSE_FAILURES - 1 {
+            record(
+                &dir,
+                Event::CloseFailed {
+                    ticket_id: "T1".to_string(),
+                    error: "InternalError".to_string(),
+                },
+            );
+        }
+        let ids = |dir: &str| -> Vec<String> {
+            unclosed_tickets(dir).into_iter().map(|(id, _)| id).collect()
+        };
+        assert_eq!(ids(&dir), vec!["T1", "T2"]);
+
+        record(
+            &dir,
+            Event::CloseFailed {
+                ticket_id: "T1".to_string(),
+                error: "InternalError".to_string(),
+            },
+        );
+        assert_eq!(close_failures(&dir, "T1"), MAX_CLOSE_FAILURES);
+        assert_eq!(ids(&dir), vec!["T2"]);
+
+        record(
+            &dir,
+            Event::TicketStatus {
+                ticket_id: "T2".to_string(),
+                status: "evaluated".to_string(),
+            },
+        );
+        assert!(ids(&dir).is_empty());
+    }
+
+    /// 按给定时间（几分钟前）写入一组历史记录
+    fn write_history(events: Vec<(i64, Event)>) -> String {
+        let dir = temp_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = chrono::Local::now();
+        let lines: Vec<String> = events
+            .into_iter()
+            .map(|(minutes_ago, event)| {
+                let time = now - chrono::Duration::minutes(minutes_ago);
+                serde_json::to_string(&Record { time, event }).unwrap() + "\n"
+            })
+            .collect();
+        std::fs::write(history_path(&dir), lines.concat()).unwrap();
+        dir
+    }
+
+    fn speed(mbps: f64) -> Event {
+        Event::SpeedTest {
+            mbps,
+            bytes: 0,
+            duration_secs: 10.0,
+            per_stream: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    fn upload(mbps: f64) -> Event {
+        Event::UploadTest {
+            mbps,
+            bytes: 0,
+            duration_secs: 10.0,
+            samples: Vec::new(),
+        }
+    }
+
+    fn probe(exceeded: bool) -> Event {
+        Event::Probe {
+            avg_ms: 30.0,
+            p95_ms: 40.0,
+            jitter_ms: 2.0,
+            loss_ratio: 0.0,
+            exceeded,
+        }
+    }
+
+    fn ticket(id: Option<&str>) -> Event {
+        Event::Ticket {
+            source: "test".to_string(),
+            ticket_id: id.map(str::to_string),
+            request_id: None,
+            error: id.is_none().then(|| "Throttling".to_string()),
+        }
+    }
+
+    fn status(id: &str, status: &str) -> Event {
+        Event::TicketStatus {
+            ticket_id: id.to_string(),
+            status: status.to_string(),
+        }
+    }
+
+    #[test]
+    fn open_tickets_follow_status_changes() {
+        let dir = write_history(vec![
+            (50, ticket(Some("T1"))),
+            (40, ticket(None)),
+            (30, ticket(Some("T2"))),
+            (20, status("T1", "pending")),
+            (10, status("T2", "completed")),
+            (5, status("T3", "dealing")),
+        ]);
+        assert_eq!(
+            open_tickets(&dir),
+            vec![
+                ("T1".to_string(), "pending".to_string()),
+                ("T3".to_string(), "dealing".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn speed_before_ticket_uses_the_last_test_before_submission() {
+        let dir = write_history(vec![
+            (50, speed(5.0)),
+            (40, speed(8.0)),
+            (30, ticket(Some("T1"))),
+            (20, speed(30.0)),
+            (10, ticket(Some("T2"))),
+        ]);
+        let cases = [("T1", Some(8.0)), ("T2", Some(30.0)), ("T3", None)];
+        for (id, expected) in cases {
+            assert_eq!(speed_before_ticket(&dir, id), expected, "{}", id);
+        }
+        let dir = write_history(vec![(10, ticket(Some("T1")))]);
+        assert_eq!(speed_before_ticket(&dir, "T1"), None);
+    }
+
+    #[test]
+    fn recently_resolved_ticket_is_the_latest_completed_status() {
+        let hour = chrono::Duration::hours(1);
+        let cases = [
+            ("刚处理完", vec![(10, status("T1", "completed"))], hour, Some("T1")),
+            ("处理完太久", vec![(120, status("T1", "completed"))], hour, None),
+            (
+                "之后又有变化",
+                vec![(20, status("T1", "completed")), (10, status("T1", "dealing"))],
+                hour,
+                None,
+            ),
+            (
+                "之后另一个工单已关闭",
+                vec![(20, status("T1", "completed")), (10, status("T2", "closed"))],
+                hour,
+                None,
+            ),
+            ("没有状态记录", vec![(10, ticket(Some("T1")))], hour, None),
+        ];
+        for (name, events, within, expected) in cases {
+            let dir = write_history(events);
+            assert_eq!(
+                recently_resolved_ticket(&dir, within).as_deref(),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn normal_streak_stops_at_slow_tests_and_probes() {
+        let since = chrono::Local::now() - chrono::Duration::minutes(100);
+        // (说明, 记录, 上传阈值, 期望的次数, 期望的第一次是几分钟前)
+        let cases = [
+            (
+                "全部正常",
+                vec![(30, speed(25.0)), (20, speed(30.0)), (10, speed(22.0))],
+                None,
+                3,
+                Some(30),
+            ),
+            (
+                "中间有一次偏低",
+                vec![(30, speed(25.0)), (20, speed(5.0)), (10, speed(22.0))],
+                None,
+                1,
+                Some(10),
+            ),
+            (
+                "最近一次偏低",
+                vec![(20, speed(25.0)), (10, speed(5.0))],
+                None,
+                0,
+                None,
+            ),
+            (
+                "早于 since 的不算",
+                vec![(200, speed(25.0)), (10, speed(22.0))],
+                None,
+                1,
+                Some(10),
+            ),
+            (
+                "上传偏低打断连续",
+                vec![(30, speed(25.0)), (20, upload(1.0)), (10, speed(22.0))],
+                Some(5.0),
+                1,
+                Some(10),
+            ),
+            (
+                "未设置上传阈值时忽略上传",
+                vec![(30, speed(25.0)), (20, upload(1.0)), (10, speed(22.0))],
+                None,
+                2,
+                Some(30),
+            ),
+            (
+                "延迟超标打断连续",
+                vec![(30, speed(25.0)), (20, probe(true)), (15, probe(false)), (10, speed(22.0))],
+                None,
+                1,
+                Some(10),
+            ),
+        ];
+        for (name, events, upload_threshold, count, first_minutes_ago) in cases {
+            let dir = write_history(events);
+            let (n, first) = normal_streak(&dir, 20.0, upload_threshold, since);
+            assert_eq!(n, count, "{}", name);
+            let now = chrono::Local::now();
+            assert_eq!(
+                first.map(|t| (now - t).num_minutes()),
+                first_minutes_ago,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn ticket_times_only_count_submitted_tickets() {
+        let dir = write_history(vec![
+            (30, ticket(Some("T1"))),
+            (20, ticket(None)),
+            (15, speed(5.0)),
+            (10, ticket(Some("T2"))),
+        ]);
+        let now = chrono::Local::now();
+        let minutes: Vec<i64> = ticket_times(&dir)
+            .into_iter()
+            .map(|t| (now - t).num_minutes())
+            .collect();
+        assert_eq!(minutes, vec![30, 10]);
+        assert!(ticket_times(&temp_dir()).is_empty());
+    }
+}
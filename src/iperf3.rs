@@ -0,0 +1,407 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::{debug, info};
+
+use crate::config::SpeedtestConfig;
+
+/// iperf3 默认端口
+const DEFAULT_PORT: u16 = 5201;
+/// 会话 cookie 长度（36 个字符 + 结尾的 0）
+const COOKIE_SIZE: usize = 37;
+/// cookie 使用的字符集
+const COOKIE_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+/// 每次读写的数据块大小，与 iperf3 的 TCP 默认值一致
+pub const BLOCK_SIZE: usize = 128 * 1024;
+/// 服务端结果 JSON 的最大长度
+const MAX_JSON_SIZE: u32 = 1024 * 1024;
+/// 建立 TCP 连接的超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 控制连接上的状态码（单个有符号字节）
+const TEST_START: i8 = 1;
+const TEST_RUNNING: i8 = 2;
+const TEST_END: i8 = 4;
+const PARAM_EXCHANGE: i8 = 9;
+const CREATE_STREAMS: i8 = 10;
+const EXCHANGE_RESULTS: i8 = 13;
+const DISPLAY_RESULTS: i8 = 14;
+const IPERF_DONE: i8 = 16;
+const ACCESS_DENIED: i8 = -1;
+const SERVER_ERROR: i8 = -2;
+
+/// 一次 iperf3 测试的控制会话
+///
+/// `start` 完成参数协商并建立数据连接，返回时服务端已进入 TEST_RUNNING；
+/// 计时结束后调用 `finish` 交换结果并结束测试。
+pub struct Session {
+    control: TcpStream,
+    reverse: bool,
+    timeout: Duration,
+}
+
+impl Session {
+    /// 连接服务端并开始测试，返回会话和各条数据连接
+    ///
+    /// `reverse` 为 true 时由服务端发送数据（测下载），否则由本机发送（测上传）。
+    pub async fn start(settings: &SpeedtestConfig, reverse: bool) -> Result<(Self, Vec<TcpStream>)> {
+        let server = settings
+            .iperf3_server
+            .as_deref()
+            .context("未配置 iperf3 服务端")?;
+        let timeout = Duration::from_secs(settings.timeout_secs);
+        let addr = resolve(server).await?;
+        let streams = settings.streams.max(1);
+
+        tokio::time::timeout(timeout, async {
+            let cookie = make_cookie();
+            let mut control = connect(settings, addr).await.context("连接 iperf3 服务端失败")?;
+            control.write_all(&cookie).await?;
+
+            let mut data = Vec::with_capacity(streams);
+            loop {
+                match read_state(&mut control).await? {
+                    PARAM_EXCHANGE => {
+                        let params = json!({
+                            "tcp": true,
+                            "omit": 0,
+                            // 服务端按这个时长做超时保护，本地计时从第一个字节开始，多留一秒
+                            "time": settings.duration_secs + 1,
+                            "num": 0,
+                            "blockcount": 0,
+                            "parallel": streams,
+                            "reverse": reverse,
+                            "len": BLOCK_SIZE,
+                            "pacing_timer": 1000,
+                            "client_version": "3.17",
+                        });
+                        write_json(&mut control, &params).await?;
+                    }
+                    CREATE_STREAMS => {
+                        for _ in 0..streams {
+                            let mut stream = connect(settings, addr).await.context("建立 iperf3 数据连接失败")?;
+                            stream.write_all(&cookie).await?;
+                            data.push(stream);
+                        }
+                    }
+                    TEST_START => {}
+                    TEST_RUNNING => break,
+                    other => return Err(state_error(&mut control, other).await),
+                }
+            }
+            info!(
+                "iperf3 测试已开始: {}（{} 路，{}）",
+                addr,
+                streams,
+                if reverse { "反向，服务端发送" } else { "本机发送" }
+            );
+            Ok((
+                Self {
+                    control,
+                    reverse,
+                    timeout,
+                },
+                data,
+            ))
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("iperf3 握手超时：{}秒内未开始测试", settings.timeout_secs))?
+    }
+
+    /// 通知服务端测试结束并交换结果
+    ///
+    /// `per_stream_bytes` 为各数据连接在计时窗口内传输的字节数，顺序与 `start` 返回的连接一致。
+    pub async fn finish(mut self, per_stream_bytes: &[u64], elapsed_secs: f64) -> Result<()> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, async {
+            self.control.write_all(&[TEST_END as u8]).await?;
+            loop {
+                match read_state(&mut self.control).await? {
+                    EXCHANGE_RESULTS => {
+                        let results = self.local_results(per_stream_bytes, elapsed_secs);
+                        write_json(&mut self.control, &results).await?;
+                        let remote = read_json(&mut self.control).await?;
+                        log_remote_results(&remote);
+                    }
+                    DISPLAY_RESULTS => {
+                        self.control.write_all(&[IPERF_DONE as u8]).await?;
+                        return Ok(());
+                    }
+                    state @ (ACCESS_DENIED | SERVER_ERROR) => {
+                        return Err(state_error(&mut self.control, state).await)
+                    }
+                    // 其余状态与收尾无关
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("iperf3 结束测试超时"))?
+    }
+
+    /// 本机一侧的结果，格式与 iperf3 客户端发送的一致
+    fn local_results(&self, per_stream_bytes: &[u64], elapsed_secs: f64) -> serde_json::Value {
+        let sender = !self.reverse;
+        let streams: Vec<serde_json::Value> = per_stream_bytes
+            .iter()
+            .enumerate()
+            .map(|(idx, bytes)| {
+                json!({
+                    "id": stream_id(idx),
+                    "bytes": bytes,
+                    "retransmits": if sender { 0 } else { -1 },
+                    "jitter": 0,
+                    "errors": 0,
+                    "packets": 0,
+                    "start_time": 0,
+                    "end_time": elapsed_secs,
+                })
+            })
+            .collect();
+        json!({
+            "cpu_util_total": 0,
+            "cpu_util_user": 0,
+            "cpu_util_system": 0,
+            "sender_has_retransmits": if sender { 0 } else { -1 },
+            "streams": streams,
+        })
+    }
+}
+
+/// iperf3 的流编号：第一条为 1，之后从 3 开始递增
+fn stream_id(idx: usize) -> usize {
+    if idx == 0 {
+        1
+    } else {
+        idx + 2
+    }
+}
+
+fn make_cookie() -> [u8; COOKIE_SIZE] {
+    let mut cookie = [0u8; COOKIE_SIZE];
+    for b in cookie.iter_mut().take(COOKIE_SIZE - 1) {
+        *b = COOKIE_CHARS[rand::random::<usize>() % COOKIE_CHARS.len()];
+    }
+    cookie
+}
+
+/// 解析服务端地址，未写端口时使用 5201
+async fn resolve(server: &str) -> Result<SocketAddr> {
+    let addrs = match tokio::net::lookup_host(server).await {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(_) => tokio::net::lookup_host((server, DEFAULT_PORT))
+            .await
+            .with_context(|| format!("解析 iperf3 服务端地址 {} 失败", server))?
+            .collect(),
+    };
+    addrs
+        .into_iter()
+        .next()
+        .with_context(|| format!("解析 iperf3 服务端地址 {} 失败", server))
+}
+
+/// 按测速配置的源 IP 和网卡建立 TCP 连接
+async fn connect(settings: &SpeedtestConfig, addr: SocketAddr) -> Result<TcpStream> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    if let Some(interface) = &settings.bind_interface {
+        bind_device(&socket, interface)?;
+    }
+    if let Some(ip) = settings.source_ip {
+        socket
+            .bind(SocketAddr::new(ip, 0))
+            .with_context(|| format!("绑定源 IP {} 失败", ip))?;
+    }
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, socket.connect(addr))
+        .await
+        .map_err(|_| anyhow::anyhow!("连接 {} 超时", addr))??;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &TcpSocket, interface: &str) -> Result<()> {
+    socket
+        .bind_device(Some(interface.as_bytes()))
+        .with_context(|| format!("绑定网卡 {} 失败", interface))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &TcpSocket, interface: &str) -> Result<()> {
+    anyhow::bail!("当前系统不支持 iperf3 测速绑定网卡 {}，请改用 source_ip", interface)
+}
+
+async fn read_state(control: &mut TcpStream) -> Result<i8> {
+    let mut buf = [0u8; 1];
+    control
+        .read_exact(&mut buf)
+        .await
+        .context("iperf3 服务端断开了控制连接")?;
+    Ok(buf[0] as i8)
+}
+
+/// 把异常状态转成错误；SERVER_ERROR 后面跟着两个 4 字节的错误码
+async fn state_error(control: &mut TcpStream, state: i8) -> anyhow::Error {
+    match state {
+        ACCESS_DENIED => anyhow::anyhow!("iperf3 服务端正忙，拒绝了本次测试（同一时间只能进行一个测试）"),
+        SERVER_ERROR => {
+            let code = control.read_i32().await.unwrap_or(0);
+            let errno = control.read_i32().await.unwrap_or(0);
+            anyhow::anyhow!("iperf3 服务端出错（错误码 {}，errno {}）", code, errno)
+        }
+        other => anyhow::anyhow!("iperf3 服务端返回了意外的状态 {}", other),
+    }
+}
+
+/// 写入 4 字节大端长度 + JSON
+async fn write_json(control: &mut TcpStream, value: &serde_json::Value) -> Result<()> {
+    let body = serde_json::to_vec(value)?;
+    control.write_u32(body.len() as u32).await?;
+    control.write_all(&body).await?;
+    Ok(())
+}
+
+async fn read_json(control: &mut TcpStream) -> Result<serde_json::Value> {
+    let len = control.read_u32().await.context("读取 iperf3 结果失败")?;
+    if len > MAX_JSON_SIZE {
+        anyhow::bail!("iperf3 结果过大（{} 字节）", len);
+    }
+    let mut body = vec![0u8; len as usize];
+    control.read_exact(&mut body).await.context("读取 iperf3 结果失败")?;
+    serde_json::from_slice(&body).context("解析 iperf3 结果失败")
+}
+
+/// 记录服务端统计的字节数和重传次数，仅用于排查
+fn log_remote_results(remote: &serde_json::Value) {
+    let streams = remote["streams"].as_array().cloned().unwrap_or_default();
+    let bytes: u64 = streams.iter().filter_map(|s| s["bytes"].as_u64()).sum();
+    let retransmits: i64 = streams
+        .iter()
+        .filter_map(|s| s["retransmits"].as_i64())
+        .filter(|r| *r > 0)
+        .sum();
+    debug!(
+        "iperf3 服务端统计: {:.2} MB，重传 {} 次",
+        bytes as f64 / 1_000_000.0,
+        retransmits
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SpeedtestBackend;
+    use tokio::net::TcpListener;
+
+    fn settings(server: String, streams: usize) -> SpeedtestConfig {
+        SpeedtestConfig {
+            backend: SpeedtestBackend::Iperf3,
+            iperf3_server: Some(server),
+            download_urls: Vec::new(),
+            upload_url: String::new(),
+            duration_secs: 1,
+            timeout_secs: 5,
+            user_agent: String::new(),
+            streams,
+            bind_interface: None,
+            source_ip: None,
+        }
+    }
+
+    async fn write_state(control: &mut TcpStream, state: i8) {
+        control.write_all(&[state as u8]).await.unwrap();
+    }
+
+    /// 模拟 iperf3 服务端走完一次完整的控制流程，返回客户端发来的参数和结果
+    async fn stub_server(listener: TcpListener) -> (serde_json::Value, serde_json::Value) {
+        let (mut control, _) = listener.accept().await.unwrap();
+        let mut cookie = [0u8; COOKIE_SIZE];
+        control.read_exact(&mut cookie).await.unwrap();
+
+        write_state(&mut control, PARAM_EXCHANGE).await;
+        let params = read_json(&mut control).await.unwrap();
+
+        write_state(&mut control, CREATE_STREAMS).await;
+        let mut data = Vec::new();
+        for _ in 0..params["parallel"].as_u64().unwrap() {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut stream_cookie = [0u8; COOKIE_SIZE];
+            stream.read_exact(&mut stream_cookie).await.unwrap();
+            assert_eq!(stream_cookie, cookie, "数据连接应发送与控制连接相同的 cookie");
+            data.push(stream);
+        }
+
+        write_state(&mut control, TEST_START).await;
+        write_state(&mut control, TEST_RUNNING).await;
+
+        assert_eq!(read_state(&mut control).await.unwrap(), TEST_END);
+        write_state(&mut control, EXCHANGE_RESULTS).await;
+        let results = read_json(&mut control).await.unwrap();
+        write_json(&mut control, &json!({ "streams": [{ "bytes": 1000, "retransmits": 2 }] }))
+            .await
+            .unwrap();
+        write_state(&mut control, DISPLAY_RESULTS).await;
+        assert_eq!(read_state(&mut control).await.unwrap(), IPERF_DONE);
+        (params, results)
+    }
+
+    #[tokio::test]
+    async fn handshake_and_result_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let stub = tokio::spawn(stub_server(listener));
+
+        let (session, streams) = Session::start(&settings(server, 2), true).await.unwrap();
+        assert_eq!(streams.len(), 2);
+        session.finish(&[100, 200], 1.0).await.unwrap();
+
+        let (params, results) = stub.await.unwrap();
+        assert_eq!(params["parallel"], 2);
+        assert_eq!(params["reverse"], true);
+        assert_eq!(params["len"], BLOCK_SIZE);
+        assert_eq!(params["time"], 2);
+        // 反向测试时本机是接收方，没有重传统计
+        assert_eq!(results["sender_has_retransmits"], -1);
+        let ids: Vec<u64> = results["streams"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(results["streams"][1]["bytes"], 200);
+    }
+
+    #[tokio::test]
+    async fn busy_and_failing_servers_are_reported() {
+        for (state, expected) in [(ACCESS_DENIED, "正忙"), (SERVER_ERROR, "错误码 7")] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = listener.local_addr().unwrap().to_string();
+            tokio::spawn(async move {
+                let (mut control, _) = listener.accept().await.unwrap();
+                let mut cookie = [0u8; COOKIE_SIZE];
+                control.read_exact(&mut cookie).await.unwrap();
+                write_state(&mut control, state).await;
+                control.write_i32(7).await.unwrap();
+                control.write_i32(111).await.unwrap();
+            });
+
+            let err = Session::start(&settings(server, 1), false).await.err().unwrap();
+            assert!(format!("{:#}", err).contains(expected), "{:#}", err);
+        }
+    }
+
+    #[test]
+    fn cookie_is_nul_terminated_base32() {
+        let cookie = make_cookie();
+        assert_eq!(cookie[COOKIE_SIZE - 1], 0);
+        assert!(cookie[..COOKIE_SIZE - 1].iter().all(|b| COOKIE_CHARS.contains(b)));
+    }
+}
@@ -1,11 +1,30 @@
 mod client;
+mod cms;
 mod config;
+mod credentials;
 mod feishu;
+mod followup;
+mod governor;
+mod history;
+mod iperf3;
+#[cfg(any(test, feature = "mock"))]
+#[cfg_attr(not(feature = "mock"), allow(dead_code))]
+mod mock;
+mod notify;
+mod passive;
+mod peer;
+mod probe;
+mod rpc;
+mod secrets;
 mod templates;
 mod server;
+mod shape;
 mod signer;
+mod sm3;
 mod speedtest;
+mod swas;
 mod telegram;
+mod tracker;
 
 use std::sync::Arc;
 
@@ -22,87 +41,117 @@ impl tracing_subscriber::fmt::time::FormatTime for LocalTimer {
 }
 
 /// 测速并通知飞书
-async fn check_speed_and_notify(config: config::Config, callback_server: Arc<server::CallbackServer>) {
+async fn check_speed_and_notify(
+    config: config::Config,
+    callback_server: Arc<server::CallbackServer>,
+    tracker: Arc<tracker::TicketTracker>,
+) {
     let threshold = config.speed_threshold;
     info!("开始测速，阈值: {} Mbps", threshold);
 
-    let speed = match speedtest::measure_download_speed().await {
-        Ok(s) => s,
+    let verdict = match speedtest::check_throttle(&config).await {
+        Ok(v) => v,
         Err(e) => {
             error!("测速失败: {:#}", e);
-            let msg = format!("测速失败: {:#}\n保险起见请手动检查带宽情况", e);
-            if let Some(webhook) = &config.feishu_webhook_url {
-                let _ = feishu::send_text(webhook, &msg).await;
-            }
-            if let (Some(token), Some(chat_id)) = (&config.telegram_bot_token, config.telegram_chat_id) {
-                let _ = telegram::send_message(token, chat_id, &format!("❌ {}", msg)).await;
-            }
+            let msg = format!("❌ 测速失败: {:#}\n保险起见请手动检查带宽情况", e);
+            notify::broadcast(&config, &msg).await;
             return;
         }
     };
+    let speed = verdict.mbps;
 
     // 发送测速结果到飞书
-    if speed < threshold {
-        warn!("下载速度 {:.2} Mbps 低于阈值 {} Mbps", speed, threshold);
+    if verdict.throttled {
+        warn!(
+            "判定为{}（下载 {:.2} Mbps，阈值 {} Mbps）",
+            verdict.alert_reasons(),
+            speed,
+            threshold
+        );
+        let alert = format!(
+            "⚠️ 带宽限速告警（{}）\n下载速度: {:.2} Mbps（阈值: {} Mbps）{}",
+            verdict.alert_reasons(),
+            speed,
+            threshold,
+            verdict.detail()
+        );
+
+        // 流量包用完导致的限速不提交也不追加回复
+        if let Err(e) = swas::check_traffic(&config).await {
+            warn!("{:#}", e);
+            notify::broadcast(&config, &format!("{}\n📦 {:#}", alert, e)).await;
+            return;
+        }
+
+        // 已有工单时不再重复提交（处理中则跳过，已处理仍限速则追加回复）
+        if let Some(note) = followup::handle_existing_ticket(&config, &tracker, speed).await {
+            notify::broadcast(&config, &format!("{}\n{}", alert, note)).await;
+            return;
+        }
 
         // 使用多样化模板生成工单内容
         let mut cfg = config.clone();
-        cfg.ticket_title = templates::random_title();
-        cfg.ticket_description = templates::random_description(speed);
+        let ctx = config.ticket_context();
+        cfg.ticket_title = templates::random_title(&ctx);
+        cfg.ticket_description = verdict.ticket_description(&ctx);
         info!("工单标题: {}", cfg.ticket_title);
 
         if config.auto_submit {
             // 自动提交模式：直接提交工单
             info!("auto_submit 已开启，直接提交工单");
             let client = client::WorkorderClient::new(cfg.clone());
-            match client.submit_ticket().await {
-                Ok(ticket_id) => {
-                    let msg = format!("⚠️ 带宽限速告警\n下载速度: {:.2} Mbps（阈值: {} Mbps）\n✅ 已自动提交工单: {}", speed, threshold, ticket_id);
+            match client.submit_ticket("auto").await {
+                Ok(ticket) => {
+                    let ticket_id = ticket.ticket_id;
+                    let msg = format!("{}\n✅ 已自动提交工单: {}", alert, ticket_id);
                     info!("工单提交成功，工单号: {}", ticket_id);
-                    if let Some(webhook) = &cfg.feishu_webhook_url {
-                        let _ = feishu::send_text(webhook, &msg).await;
-                    }
-                    if let (Some(token), Some(chat_id)) = (&cfg.telegram_bot_token, cfg.telegram_chat_id) {
-                        let _ = telegram::send_message(token, chat_id, &msg).await;
-                    }
+                    notify::broadcast(&cfg, &msg).await;
+                    tracker.track(&ticket_id).await;
                 }
                 Err(e) => {
-                    let msg = format!("⚠️ 带宽限速告警\n下载速度: {:.2} Mbps（阈值: {} Mbps）\n❌ 自动提交工单失败: {:#}", speed, threshold, e);
+                    let msg = format!("{}\n❌ 自动提交工单失败: {:#}", alert, e);
                     error!("工单提交失败: {:#}", e);
-                    if let Some(webhook) = &cfg.feishu_webhook_url {
-                        let _ = feishu::send_text(webhook, &msg).await;
-                    }
-                    if let (Some(token), Some(chat_id)) = (&cfg.telegram_bot_token, cfg.telegram_chat_id) {
-                        let _ = telegram::send_message(token, chat_id, &msg).await;
-                    }
+                    notify::broadcast(&cfg, &msg).await;
                 }
             }
+        } else if let Err(e) = governor::check(&cfg) {
+            // 审批模式下已达提交上限，不再发审批卡片
+            let msg = format!("{}\n⏸ {:#}", alert, e);
+            warn!("{:#}", e);
+            notify::broadcast(&cfg, &msg).await;
         } else if cfg.feishu_webhook_url.is_some() {
             // 审批模式：发飞书卡片等待点击
             let webhook = cfg.feishu_webhook_url.clone().unwrap();
             if let Some(callback_url) = cfg.callback_url.clone() {
                 let token = callback_server.add_pending(cfg).await;
                 let approve_url = server::CallbackServer::approve_url(&callback_url, &token, &config.callback_secret);
-                if let Err(e) = feishu::send_throttle_card(&webhook, speed, threshold, &approve_url).await {
+                if let Err(e) = feishu::send_throttle_card(&webhook, &verdict, threshold, &approve_url).await {
                     error!("飞书卡片发送失败: {:#}", e);
                 }
             } else {
-                let msg = format!(
-                    "⚠️ 带宽限速告警\n下载速度: {:.2} Mbps（阈值: {} Mbps）\n未配置 callback_url，请手动提交工单",
-                    speed, threshold
-                );
+                let msg = format!("{}\n未配置 callback_url，请手动提交工单", alert);
                 let _ = feishu::send_text(&webhook, &msg).await;
             }
         }
+    } else if verdict.congested {
+        let msg = format!(
+            "🌫 下载速度 {:.2} Mbps 低于阈值 {} Mbps，但吞吐波动明显，判定为网络拥塞，暂不提交工单{}",
+            speed,
+            threshold,
+            verdict.detail()
+        );
+        info!("{}", msg);
+        notify::broadcast(&config, &msg).await;
     } else {
-        let msg = format!("✅ 测速正常: {:.2} Mbps（阈值: {} Mbps）", speed, threshold);
+        let msg = format!(
+            "✅ 测速正常: {:.2} Mbps（阈值: {} Mbps）{}",
+            speed,
+            threshold,
+            verdict.detail()
+        );
         info!("{}", msg);
-        if let Some(webhook) = &config.feishu_webhook_url {
-            let _ = feishu::send_text(webhook, &msg).await;
-        }
-        if let (Some(token), Some(chat_id)) = (&config.telegram_bot_token, config.telegram_chat_id) {
-            let _ = telegram::send_message(token, chat_id, &msg).await;
-        }
+        notify::broadcast(&config, &msg).await;
+        followup::close_recovered_tickets(&config, &tracker, speed).await;
     }
 }
 
@@ -126,21 +175,56 @@ async fn main() -> Result<()> {
         println!("  --submit      直接提交工单（跳过测速）");
         println!("  --speedtest, -s  仅测速，不提交工单");
         println!("  --list, -l    查询产品和分类信息");
+        println!("  --tickets     查询最近提交的工单及状态");
+        println!("  --history     查看最近的测速、工单和审批记录");
+        println!("  --serve-speedtest  只运行对端测速服务（/__down、/__up），供其他主机测速");
+        println!("  --mock-workorder   在 api.endpoint 上运行本地模拟工单服务（需以 --features mock 编译）");
+        println!("  --encrypt     从标准输入读取一个值，输出可写入 config.json 的加密值");
         println!("  --help, -h    显示帮助信息");
         println!("\n无参数时进入定时任务模式，按 cron 表达式定期测速并处理。");
         println!("\n配置: 通过 config.json 或环境变量设置，详见 config.example.json");
         return Ok(());
     }
 
+    // 加密配置值（输出只包含加密结果，放在打印日志之前）
+    if args.iter().any(|a| a == "--encrypt") {
+        return secrets::encrypt_command();
+    }
+
     info!("=== 阿里云自动提交工单工具 ===");
-    let config = config::Config::load()?;
+
+    // 对端测速服务模式（不需要阿里云凭证）
+    if args.iter().any(|a| a == "--serve-speedtest") {
+        let (port, secret) = config::Config::load_peer_server()?;
+        peer::serve(port, secret).await;
+        return Ok(());
+    }
+
+    let mut config = config::Config::load()?;
+
+    // 本地模拟工单服务模式（离线调试提交流程）
+    if args.iter().any(|a| a == "--mock-workorder") {
+        #[cfg(feature = "mock")]
+        mock::serve(&config).await;
+        #[cfg(not(feature = "mock"))]
+        error!("当前程序未包含模拟工单服务，请使用 cargo build --features mock 重新编译");
+        return Ok(());
+    }
+
+    // 配置了实例 ID 时从 SWAS 查询地域和套餐带宽，用于推算阈值和生成工单文案
+    if let Some(instance_id) = config.instance_id.clone() {
+        match swas::SwasClient::new(&config).describe_instance(&instance_id).await {
+            Ok(instance) => config.apply_instance(instance),
+            Err(e) => warn!("查询实例信息失败，沿用配置的阈值和工单文案: {:#}", e),
+        }
+    }
 
     // 直接提交工单（跳过测速）
     if args.iter().any(|a| a == "--submit") {
         info!("直接提交模式（跳过测速）");
-        let client = client::WorkorderClient::new(config);
-        match client.submit_ticket().await {
-            Ok(ticket_id) => info!("工单提交成功，工单号: {}", ticket_id),
+        let client = client::WorkorderClient::new(config.clone());
+        match client.submit_ticket("cli").await {
+            Ok(ticket) => info!("工单提交成功，工单号: {}", ticket.ticket_id),
             Err(e) => error!("工单提交失败: {:#}", e),
         }
         return Ok(());
@@ -163,29 +247,105 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    // 工单查询模式
+    if args.iter().any(|a| a == "--tickets") {
+        info!("工单查询模式：列出最近的工单");
+        let client = client::WorkorderClient::new(config);
+        match client.list_tickets(None, 1, 20).await {
+            Ok((tickets, total)) => {
+                info!("共 {} 个工单，最近 {} 个:", total, tickets.len());
+                for t in &tickets {
+                    info!(
+                        "  {} {} [{}] {}",
+                        t.created_at(),
+                        t.id.as_deref().unwrap_or("-"),
+                        client::status_label(t.status()),
+                        t.title.as_deref().unwrap_or("-")
+                    );
+                }
+            }
+            Err(e) => error!("查询工单失败: {:#}", e),
+        }
+        return Ok(());
+    }
+
+    // 历史记录查询
+    if args.iter().any(|a| a == "--history") {
+        let records = history::recent(&config.data_dir, 30);
+        if records.is_empty() {
+            info!("暂无历史记录（数据目录: {}）", config.data_dir);
+        }
+        for r in records.iter().rev() {
+            info!("  {}", r.summary());
+        }
+        return Ok(());
+    }
+
     // 仅测速模式
     if args.iter().any(|a| a == "--speedtest" || a == "-s") {
         info!("仅测速模式");
-        match speedtest::measure_download_speed().await {
-            Ok(speed) => info!("下载速度: {:.2} Mbps", speed),
+        if config.probe.enabled() {
+            let report = probe::run(&config.probe).await;
+            history::record_probe(&config.data_dir, &report);
+            info!("延迟探测:{}", report.detail());
+        }
+        match speedtest::measure_download_speed(&config.speedtest).await {
+            Ok(sample) => {
+                history::record_speed(&config.data_dir, &sample);
+                let shape = shape::classify(&[&sample.series], config.speed_threshold)
+                    .map(|s| s.line())
+                    .unwrap_or_default();
+                info!("下载速度: {:.2} Mbps{}{}", sample.mbps, sample.streams_line(), shape);
+            }
             Err(e) => error!("测速失败: {:#}", e),
         }
+        if config.upload_threshold.is_some() {
+            match speedtest::measure_upload_speed(&config.speedtest).await {
+                Ok(sample) => {
+                    history::record_upload(&config.data_dir, &sample);
+                    info!("上传速度: {:.2} Mbps{}", sample.mbps, sample.streams_line());
+                }
+                Err(e) => error!("上传测速失败: {:#}", e),
+            }
+        }
         return Ok(());
     }
 
+    // 工单状态跟踪
+    let tracker = Arc::new(tracker::TicketTracker::new(config.clone()));
+    {
+        let tracker = tracker.clone();
+        tokio::spawn(async move { tracker.run().await });
+    }
+
     // 创建回调服务
-    let (callback_server, trigger_rx) = server::CallbackServer::new(config.callback_secret.clone());
+    let (callback_server, trigger_rx) =
+        server::CallbackServer::new(&config, tracker.clone());
     let callback_server = Arc::new(callback_server);
 
+    // 被动带宽监测（发现流量平台时走手动触发通道做主动测速确认）
+    let monitor = config.passive.interface.as_ref().map(|_| {
+        let monitor = Arc::new(passive::PassiveMonitor::new(config.clone()));
+        let sender = callback_server.check_sender();
+        tokio::spawn(monitor.clone().run(sender));
+        monitor
+    });
+
+    // 云监控带宽检查（同样走手动触发通道）
+    if config.cms.enabled && config.cms.check_interval_minutes > 0 && config.instance.is_some() {
+        tokio::spawn(cms::run(config.clone(), callback_server.check_sender()));
+    }
+
     // 监听手动触发信号，执行完整流程（测速 → 判断 → 通知/提交工单）
     fn spawn_trigger_listener(
         mut rx: tokio::sync::mpsc::Receiver<()>,
         config: config::Config,
         callback_server: Arc<server::CallbackServer>,
+        tracker: Arc<tracker::TicketTracker>,
     ) {
         tokio::spawn(async move {
             while rx.recv().await.is_some() {
-                check_speed_and_notify(config.clone(), callback_server.clone()).await;
+                check_speed_and_notify(config.clone(), callback_server.clone(), tracker.clone()).await;
             }
         });
     }
@@ -201,11 +361,13 @@ async fn main() -> Result<()> {
         // 启动 Telegram Bot
         if config.telegram_bot_token.is_some() {
             let tg_cfg = config.clone();
-            tokio::spawn(async move { telegram::start_bot(tg_cfg).await });
+            let tg_tracker = tracker.clone();
+            let tg_monitor = monitor.clone();
+            tokio::spawn(async move { telegram::start_bot(tg_cfg, tg_tracker, tg_monitor).await });
         }
 
-        spawn_trigger_listener(trigger_rx, config.clone(), callback_server.clone());
-        check_speed_and_notify(config, callback_server).await;
+        spawn_trigger_listener(trigger_rx, config.clone(), callback_server.clone(), tracker.clone());
+        check_speed_and_notify(config, callback_server, tracker).await;
 
         info!("等待回调或手动触发（按 Ctrl+C 退出）...");
         tokio::signal::ctrl_c().await?;
@@ -228,19 +390,34 @@ async fn main() -> Result<()> {
     tokio::spawn(async move { srv.start(port).await });
 
     // 启动手动触发监听
-    spawn_trigger_listener(trigger_rx, config.clone(), callback_server.clone());
+    spawn_trigger_listener(trigger_rx, config.clone(), callback_server.clone(), tracker.clone());
 
     let mut sched = JobScheduler::new().await?;
 
     let config_for_tg = config.clone();
     let cron_expr = config.cron_expression.clone();
     let cb_server = callback_server.clone();
+    let job_tracker = tracker.clone();
+    let job_monitor = monitor.clone();
     let job = Job::new_async_tz(cron_expr.as_str(), chrono::Local, move |_uuid, _lock| {
         let cfg = config.clone();
         let srv = cb_server.clone();
+        let trk = job_tracker.clone();
+        let mon = job_monitor.clone();
         Box::pin(async move {
             info!("定时任务触发");
-            check_speed_and_notify(cfg, srv).await;
+            // 启用被动监测时，主动测速只在发现流量平台或手动触发时做确认；
+            // 仍有待恢复确认的工单时照常测速，用于自动关单
+            if let Some(m) = &mon {
+                if history::unclosed_tickets(&cfg.data_dir).is_empty() {
+                    match m.summary() {
+                        Some(s) => info!("被动监测中（{}），跳过定时主动测速", s.line()),
+                        None => info!("被动监测中，跳过定时主动测速"),
+                    }
+                    return;
+                }
+            }
+            check_speed_and_notify(cfg, srv, trk).await;
         })
     })?;
 
@@ -249,7 +426,7 @@ async fn main() -> Result<()> {
 
     // 启动 Telegram Bot
     if config_for_tg.telegram_bot_token.is_some() {
-        tokio::spawn(async move { telegram::start_bot(config_for_tg).await });
+        tokio::spawn(async move { telegram::start_bot(config_for_tg, tracker, monitor).await });
     }
 
     info!("定时任务已启动，等待下次执行...");
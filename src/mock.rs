@@ -0,0 +1,450 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::Router;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::credentials::Credentials;
+use crate::signer::AliyunSigner;
+
+/// 签名时间与服务端时间允许的最大偏差（秒），与阿里云一致
+const MAX_CLOCK_SKEW_SECS: i64 = 15 * 60;
+
+/// 模拟的轻量应用服务器产品
+const PRODUCT_ID: u64 = 14278;
+/// 模拟的网络带宽工单分类
+const CATEGORY_ID: u64 = 80793;
+
+/// 模拟工单服务的状态
+struct MockState {
+    access_key_id: String,
+    security_token: Option<String>,
+    signer: AliyunSigner,
+    api_version: String,
+    /// 已使用过的 nonce，重复使用时返回 SignatureNonceUsed
+    nonces: Mutex<HashSet<String>>,
+    /// 已创建的工单：(工单号, 标题, 状态)
+    tickets: Mutex<Vec<(String, String, String)>>,
+}
+
+/// 本地模拟的阿里云工单服务
+///
+/// 按 api.signature_algorithm 校验签名（使用 `credentials` 的 AccessKey 和 SecurityToken），实现 ListProducts、
+/// ListCategories、CreateTicket、ListTickets、GetTicket、ReplyTicket、CloseTicket，
+/// 返回固定的产品和分类数据，用于离线走通提交工单的完整流程。写操作只接受 POST 表单。
+pub fn router(config: &Config, credentials: Credentials) -> Router {
+    let mut algorithm = config.api.signature_algorithm;
+    if credentials.security_token.is_some() {
+        algorithm = algorithm.for_temporary_credentials();
+    }
+    let state = Arc::new(MockState {
+        signer: AliyunSigner::new(
+            credentials.access_key_id.clone(),
+            credentials.access_key_secret,
+        )
+        .with_algorithm(algorithm),
+        access_key_id: credentials.access_key_id,
+        security_token: credentials.security_token,
+        api_version: config.api_version.clone(),
+        nonces: Mutex::new(HashSet::new()),
+        tickets: Mutex::new(Vec::new()),
+    });
+    Router::new().route("/", any(handle)).with_state(state)
+}
+
+/// 运行模拟工单服务（`--mock-workorder` 模式），监听配置中 api.endpoint 的地址
+pub async fn serve(config: &Config) {
+    let credentials = match config.credentials.get().await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("模拟工单服务启动失败，无法获取凭证: {:#}", e);
+            return;
+        }
+    };
+    let addr = config.endpoint.clone();
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!(
+                "模拟工单服务启动失败（api.endpoint 需为本机地址，如 127.0.0.1:18080）: {}",
+                e
+            );
+            return;
+        }
+    };
+    info!("模拟工单服务已启动: http://{}/", addr);
+    if config.api.scheme != "http" {
+        warn!("模拟服务只支持 http，请把 api.scheme 设为 http 后再运行其他模式");
+    }
+
+    if let Err(e) = axum::serve(listener, router(config, credentials)).await {
+        error!("模拟工单服务异常退出: {}", e);
+    }
+}
+
+/// 阿里云风格的错误响应
+fn api_error(status: StatusCode, code: &str, message: &str) -> Response {
+    let body = json!({
+        "Code": code,
+        "Message": message,
+        "RequestId": uuid::Uuid::new_v4().to_string().to_uppercase(),
+    });
+    let date = chrono::Utc::now().to_rfc2822();
+    (status, [(header::DATE, date)], axum::Json(body)).into_response()
+}
+
+/// 工单接口的成功响应
+fn api_ok(data: Value) -> Response {
+    axum::Json(json!({
+        "Code": 200,
+        "Success": true,
+        "Message": "success",
+        "RequestId": uuid::Uuid::new_v4().to_string().to_uppercase(),
+        "Data": data,
+    }))
+    .into_response()
+}
+
+async fn handle(
+    State(state): State<Arc<MockState>>,
+    method: Method,
+    Query(query): Query<BTreeMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let body = String::from_utf8_lossy(&body).into_owned();
+    if let Err(resp) = state.verify(&method, &query, &headers, &body).await {
+        return resp;
+    }
+
+    let header_str = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let action = header_str("x-acs-action");
+
+    // GET 参数在 query 中，POST 表单参数在 body 中
+    let mut params = query;
+    let form = header_str("content-type").starts_with("application/x-www-form-urlencoded");
+    if form {
+        params.extend(parse_form(&body));
+    }
+    info!("模拟工单服务: {} {:?}", action, params);
+
+    // 写操作要求用 POST 表单，和客户端的约定保持一致
+    let write = matches!(action.as_str(), "CreateTicket" | "ReplyTicket" | "CloseTicket");
+    if write && !(method == Method::POST && form) {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            "UnsupportedHTTPMethod",
+            &format!("{} must be sent as a POST form.", action),
+        );
+    }
+
+    match action.as_str() {
+        "ListProducts" => api_ok(json!([{
+            "DirectoryId": 1,
+            "DirectoryName": "弹性计算",
+            "ProductList": [
+                { "ProductId": 10001, "ProductName": "云服务器 ECS" },
+                { "ProductId": PRODUCT_ID, "ProductName": "轻量应用服务器" },
+            ],
+        }])),
+        "ListCategories" => api_ok(json!([
+            { "CategoryId": 80790, "CategoryName": "购买咨询" },
+            { "CategoryId": CATEGORY_ID, "CategoryName": "网络带宽问题" },
+        ])),
+        "CreateTicket" => {
+            let Some(title) = params.get("Title").filter(|t| !t.is_empty()) else {
+                return api_error(
+                    StatusCode::BAD_REQUEST,
+                    "MissingTitle",
+                    "Title is mandatory for this action.",
+                );
+            };
+            let mut tickets = state.tickets.lock().await;
+            let id = format!("M{:08}", tickets.len() + 1);
+            tickets.push((id.clone(), title.clone(), "dealing".to_string()));
+            info!("模拟工单服务: 已创建工单 {}「{}」", id, title);
+            api_ok(json!(id))
+        }
+        "ListTickets" => {
+            let tickets = state.tickets.lock().await;
+            let list: Vec<Value> = tickets
+                .iter()
+                .filter(|(id, _, status)| {
+                    params.get("TicketId").is_none_or(|t| t == id)
+                        && params.get("TicketStatus").is_none_or(|s| s == status)
+                })
+                .map(|(id, title, status)| {
+                    json!({
+                        "Id": id,
+                        "Title": title,
+                        "TicketStatus": status,
+                        "AddTime": chrono::Utc::now().timestamp_millis(),
+                        "CategoryId": CATEGORY_ID,
+                    })
+                })
+                .collect();
+            let total = list.len();
+            let number = |name: &str, default: usize| {
+                params
+                    .get(name)
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(default)
+            };
+            let (page, size) = (number("CurrentPage", 1).max(1), number("PageSize", 10));
+            let list: Vec<Value> = list
+                .into_iter()
+                .skip((page - 1) * size)
+                .take(size)
+                .collect();
+            api_ok(json!({ "List": list, "Total": total }))
+        }
+        "GetTicket" => {
+            let id = params.get("TicketId").cloned().unwrap_or_default();
+            let tickets = state.tickets.lock().await;
+            let Some((id, title, status)) = tickets.iter().find(|(t, _, _)| *t == id) else {
+                return api_error(
+                    StatusCode::NOT_FOUND,
+                    "TicketNotFound",
+                    "The specified ticket does not exist.",
+                );
+            };
+            api_ok(json!({
+                "Id": id,
+                "Title": title,
+                "TicketStatus": status,
+                "AddTime": chrono::Utc::now().timestamp_millis(),
+                "CategoryId": CATEGORY_ID,
+            }))
+        }
+        "ReplyTicket" | "CloseTicket" => {
+            let id = params.get("TicketId").cloned().unwrap_or_default();
+            let mut tickets = state.tickets.lock().await;
+            let Some(ticket) = tickets.iter_mut().find(|(t, _, _)| *t == id) else {
+                return api_error(
+                    StatusCode::NOT_FOUND,
+                    "TicketNotFound",
+                    "The specified ticket does not exist.",
+                );
+            };
+            if action == "CloseTicket" {
+                ticket.2 = "closed".to_string();
+            }
+            api_ok(json!(true))
+        }
+        _ => api_error(
+            StatusCode::NOT_FOUND,
+            "InvalidAction.NotFound",
+            &format!("Specified api {} is not found.", action),
+        ),
+    }
+}
+
+/// 解析 `k=v&k2=v2` 形式的表单 body（与 query 的编码规则相同）
+fn parse_form(body: &str) -> BTreeMap<String, String> {
+    format!("/?{}", body)
+        .parse::<axum::http::Uri>()
+        .ok()
+        .and_then(|uri| Query::<BTreeMap<String, String>>::try_from_uri(&uri).ok())
+        .map(|Query(params)| params)
+        .unwrap_or_default()
+}
+
+impl MockState {
+    /// 按阿里云的规则校验请求：版本、时间戳、nonce、body 摘要和签名
+    async fn verify(
+        &self,
+        method: &Method,
+        query: &BTreeMap<String, String>,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Result<(), Response> {
+        let header_str = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        if header_str("x-acs-version") != Some(self.api_version.as_str()) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "InvalidVersion",
+                "Specified parameter Version is not valid.",
+            ));
+        }
+
+        let date = header_str("x-acs-date")
+            .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+            .ok_or_else(|| {
+                api_error(
+                    StatusCode::BAD_REQUEST,
+                    "MissingTimestamp",
+                    "x-acs-date is mandatory.",
+                )
+            })?;
+        if (chrono::Utc::now().timestamp() - date.timestamp()).abs() > MAX_CLOCK_SKEW_SECS {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "InvalidTimeStamp.Expired",
+                "Specified time stamp or date value is expired.",
+            ));
+        }
+
+        let nonce = header_str("x-acs-signature-nonce").unwrap_or_default();
+        if nonce.is_empty() || !self.nonces.lock().await.insert(nonce.to_string()) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "SignatureNonceUsed",
+                "Specified signature nonce was used already.",
+            ));
+        }
+
+        let content_header = self.signer.content_hash_header();
+        if header_str(content_header) != Some(self.signer.hash_payload(body).as_str()) {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "IncompleteSignature",
+                &format!(
+                    "The {} header does not match the request body.",
+                    content_header
+                ),
+            ));
+        }
+
+        // Authorization: <签名算法> Credential=AK,SignedHeaders=a;b,Signature=hex
+        let authorization = header_str("authorization").unwrap_or_default();
+        let fields: BTreeMap<&str, &str> = authorization
+            .split_once(' ')
+            .map(|(_, rest)| rest)
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|f| f.trim().split_once('='))
+            .collect();
+        if fields.get("Credential") != Some(&self.access_key_id.as_str()) {
+            return Err(api_error(
+                StatusCode::NOT_FOUND,
+                "InvalidAccessKeyId.NotFound",
+                "Specified access key is not found.",
+            ));
+        }
+
+        if header_str("x-acs-security-token") != self.security_token.as_deref() {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "InvalidSecurityToken.MismatchWithAccessKey",
+                "Specified SecurityToken mismatch with the AccessKey.",
+            ));
+        }
+
+        let signed_headers: BTreeMap<String, String> = fields
+            .get("SignedHeaders")
+            .unwrap_or(&"")
+            .split(';')
+            .filter_map(|name| Some((name.to_string(), header_str(name)?.to_string())))
+            .collect();
+        let expected = self
+            .signer
+            .sign(method.as_str(), query, &signed_headers, body)
+            .unwrap_or_default();
+        if expected != authorization {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "SignatureDoesNotMatch",
+                "The request signature does not conform to Aliyun standards.",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::WorkorderClient;
+    use crate::config::FileConfig;
+
+    /// 在随机端口上启动模拟工单服务（按 `server_secret` 校验签名），返回指向它的配置
+    async fn start_mock(server_secret: &str) -> Config {
+        let data_dir =
+            std::env::temp_dir().join(format!("aliyun-auto-ticket-test-{}", uuid::Uuid::new_v4()));
+        let mut config = Config::from_file(FileConfig {
+            access_key_id: Some("MockAccessKeyId".to_string()),
+            access_key_secret: Some("MockAccessKeySecret".to_string()),
+            data_dir: Some(data_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        config.endpoint = listener.local_addr().unwrap().to_string();
+        config.api.scheme = "http".to_string();
+        config.api.max_retries = 0;
+
+        let client_credentials = config.credentials.get().await.unwrap();
+        let credentials =
+            Credentials::new(client_credentials.access_key_id, server_secret.to_string());
+        let app = router(&config, credentials);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        config
+    }
+
+    #[tokio::test]
+    async fn submit_ticket_flow() {
+        let config = start_mock("MockAccessKeySecret").await;
+        let client = WorkorderClient::new(config.clone());
+
+        // 未配置 product_id / category_id，走 ListProducts、ListCategories 自动查找
+        let ticket = client.submit_ticket("test").await.unwrap();
+        assert_eq!(ticket.ticket_id, "M00000001");
+        assert!(ticket.request_id.is_some());
+
+        let info = client.get_ticket(&ticket.ticket_id).await.unwrap();
+        assert_eq!(info.status(), "dealing");
+        assert_eq!(info.title.as_deref(), Some(config.ticket_title.as_str()));
+        assert_eq!(info.category_id, Some(CATEGORY_ID));
+
+        client
+            .reply_ticket(&ticket.ticket_id, "仍然限速")
+            .await
+            .unwrap();
+        client.close_ticket(&ticket.ticket_id).await.unwrap();
+        let info = client.get_ticket(&ticket.ticket_id).await.unwrap();
+        assert_eq!(info.status(), "closed");
+
+        assert!(client.get_ticket("M99999999").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wrong_secret_is_rejected() {
+        let config = start_mock("AnotherAccessKeySecret").await;
+        let client = WorkorderClient::new(config);
+
+        let err = client.submit_ticket("test").await.unwrap_err();
+        let api_error = err.downcast_ref::<crate::rpc::ApiError>().unwrap();
+        assert_eq!(api_error.code.as_deref(), Some("SignatureDoesNotMatch"));
+    }
+
+    #[tokio::test]
+    async fn concurrent_submissions_respect_the_quota() {
+        let mut config = start_mock("MockAccessKeySecret").await;
+        config.max_tickets_per_day = 1;
+        let first = WorkorderClient::new(config.clone());
+        let second = WorkorderClient::new(config.clone());
+
+        // 两个入口同时审批，只有一个能通过频率检查
+        let (a, b) = tokio::join!(first.submit_ticket("feishu"), second.submit_ticket("telegram"));
+        assert_eq!([a.is_ok(), b.is_ok()].iter().filter(|ok| **ok).count(), 1);
+        let err = a.err().or(b.err()).unwrap();
+        assert!(format!("{:#}", err).contains("提交频率受限"), "{:#}", err);
+        assert_eq!(crate::history::ticket_times(&config.data_dir).len(), 1);
+    }
+}
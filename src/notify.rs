@@ -0,0 +1,12 @@
+use crate::config::Config;
+use crate::{feishu, telegram};
+
+/// 同时推送文本消息到飞书和 Telegram（未配置的渠道自动跳过）
+pub async fn broadcast(config: &Config, text: &str) {
+    if let Some(webhook) = &config.feishu_webhook_url {
+        let _ = feishu::send_text(webhook, text).await;
+    }
+    if let (Some(token), Some(chat_id)) = (&config.telegram_bot_token, config.telegram_chat_id) {
+        let _ = telegram::send_message(token, chat_id, text).await;
+    }
+}
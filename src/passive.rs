@@ -0,0 +1,376 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+use crate::config::{Config, PassiveConfig};
+use crate::notify;
+
+/// 一个采样间隔内的平均速率
+#[derive(Debug, Clone, Copy)]
+pub struct Rate {
+    pub time: chrono::DateTime<chrono::Local>,
+    /// 入方向（Mbps）
+    pub rx_mbps: f64,
+    /// 出方向（Mbps）
+    pub tx_mbps: f64,
+}
+
+/// 滚动窗口的统计
+#[derive(Debug, Clone, Copy)]
+pub struct WindowSummary {
+    /// 最近一个采样间隔的速率
+    pub latest: Rate,
+    pub avg_rx_mbps: f64,
+    pub avg_tx_mbps: f64,
+    pub peak_rx_mbps: f64,
+    pub peak_tx_mbps: f64,
+    /// 窗口实际覆盖的时长（分钟）
+    pub minutes: f64,
+}
+
+impl WindowSummary {
+    /// 单行说明，如 "↓ 9.8 / ↑ 0.3 Mbps（近 5 分钟平均 ↓ 9.6 / ↑ 0.3，峰值 ↓ 10.1 / ↑ 0.5）"
+    pub fn line(&self) -> String {
+        format!(
+            "↓ {:.1} / ↑ {:.1} Mbps（近 {:.0} 分钟平均 ↓ {:.1} / ↑ {:.1}，峰值 ↓ {:.1} / ↑ {:.1}）",
+            self.latest.rx_mbps,
+            self.latest.tx_mbps,
+            self.minutes,
+            self.avg_rx_mbps,
+            self.avg_tx_mbps,
+            self.peak_rx_mbps,
+            self.peak_tx_mbps
+        )
+    }
+}
+
+/// 检测到的流量平台
+#[derive(Debug, Clone, Copy)]
+pub struct Plateau {
+    /// "下载" 或 "上传"
+    pub direction: &'static str,
+    /// 窗口内的平均速率（Mbps）
+    pub mbps: f64,
+    /// 持续时长（分钟）
+    pub minutes: f64,
+}
+
+/// 被动带宽监测：定期读取网卡计数器，维护滚动窗口内的收发速率
+///
+/// 发现流量长时间卡在某个低于阈值的值附近时，触发一次主动测速确认。
+pub struct PassiveMonitor {
+    config: Config,
+    rates: Mutex<VecDeque<Rate>>,
+    last_trigger: Mutex<Option<Instant>>,
+}
+
+impl PassiveMonitor {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            rates: Mutex::new(VecDeque::new()),
+            last_trigger: Mutex::new(None),
+        }
+    }
+
+    fn settings(&self) -> &PassiveConfig {
+        &self.config.passive
+    }
+
+    /// 后台采样循环，检测到平台时通过 `trigger` 触发完整检测流程
+    pub async fn run(self: std::sync::Arc<Self>, trigger: mpsc::Sender<()>) {
+        let Some(interface) = self.settings().interface.clone() else {
+            return;
+        };
+        let interval = Duration::from_secs(self.settings().sample_interval_secs.max(1));
+        info!(
+            "被动监测已启动，网卡: {}，采样间隔 {} 秒，窗口 {} 分钟",
+            interface,
+            interval.as_secs(),
+            self.settings().window_minutes
+        );
+
+        let mut previous = match read_counters(&interface) {
+            Ok(c) => (Instant::now(), c),
+            Err(e) => {
+                error!("被动监测无法读取网卡计数器，已停用: {:#}", e);
+                return;
+            }
+        };
+
+        loop {
+            tokio::time::sleep(interval).await;
+            let (rx, tx) = match read_counters(&interface) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("读取网卡计数器失败: {:#}", e);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            let (prev_at, (prev_rx, prev_tx)) = previous;
+            previous = (now, (rx, tx));
+
+            // 计数器回绕或网卡重置时丢弃这一次
+            if rx < prev_rx || tx < prev_tx {
+                continue;
+            }
+            let secs = now.duration_since(prev_at).as_secs_f64();
+            let to_mbps = |bytes: u64| bytes as f64 * 8.0 / (secs * 1_000_000.0);
+            self.push(Rate {
+                time: chrono::Local::now(),
+                rx_mbps: to_mbps(rx - prev_rx),
+                tx_mbps: to_mbps(tx - prev_tx),
+            });
+
+            if let Some(plateau) = self.detect_plateau() {
+                self.on_plateau(plateau, &trigger).await;
+            }
+        }
+    }
+
+    fn push(&self, rate: Rate) {
+        let window = chrono::Duration::minutes(self.settings().window_minutes as i64);
+        let mut rates = self.rates.lock().unwrap();
+        rates.push_back(rate);
+        while rates
+            .front()
+            .is_some_and(|r| rate.time - r.time > window)
+        {
+            rates.pop_front();
+        }
+    }
+
+    /// 当前滚动窗口的统计（尚无采样时返回 None）
+    pub fn summary(&self) -> Option<WindowSummary> {
+        let rates = self.rates.lock().unwrap();
+        let latest = *rates.back()?;
+        let first = rates.front()?;
+        let n = rates.len() as f64;
+        Some(WindowSummary {
+            latest,
+            avg_rx_mbps: rates.iter().map(|r| r.rx_mbps).sum::<f64>() / n,
+            avg_tx_mbps: rates.iter().map(|r| r.tx_mbps).sum::<f64>() / n,
+            peak_rx_mbps: rates.iter().map(|r| r.rx_mbps).fold(0.0, f64::max),
+            peak_tx_mbps: rates.iter().map(|r| r.tx_mbps).fold(0.0, f64::max),
+            minutes: (latest.time - first.time).num_seconds() as f64 / 60.0
+                + self.settings().sample_interval_secs as f64 / 60.0,
+        })
+    }
+
+    /// 检测流量平台：窗口已满，每个采样都有明显流量，且都落在低于阈值的同一个值附近
+    fn detect_plateau(&self) -> Option<Plateau> {
+        let settings = self.settings();
+        let rates = self.rates.lock().unwrap();
+        let first = rates.front()?;
+        let last = rates.back()?;
+        let minutes = (last.time - first.time).num_seconds() as f64 / 60.0
+            + settings.sample_interval_secs as f64 / 60.0;
+        if minutes < settings.window_minutes as f64 {
+            return None;
+        }
+
+        let upload_threshold = self
+            .config
+            .upload_threshold
+            .unwrap_or(self.config.speed_threshold);
+        let directions: [(&'static str, f64, Vec<f64>); 2] = [
+            (
+                "下载",
+                self.config.speed_threshold,
+                rates.iter().map(|r| r.rx_mbps).collect(),
+            ),
+            (
+                "上传",
+                upload_threshold,
+                rates.iter().map(|r| r.tx_mbps).collect(),
+            ),
+        ];
+
+        directions
+            .into_iter()
+            .find_map(|(direction, threshold, values)| {
+                plateau_mean(&values, threshold, settings).map(|mbps| Plateau {
+                    direction,
+                    mbps,
+                    minutes,
+                })
+            })
+    }
+
+    async fn on_plateau(&self, plateau: Plateau, trigger: &mpsc::Sender<()>) {
+        let cooldown = Duration::from_secs(self.settings().cooldown_minutes * 60);
+        {
+            let mut last = self.last_trigger.lock().unwrap();
+            if last.is_some_and(|t| t.elapsed() < cooldown) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+
+        warn!(
+            "被动监测: {}流量已持续 {:.0} 分钟卡在约 {:.1} Mbps",
+            plateau.direction, plateau.minutes, plateau.mbps
+        );
+        let msg = format!(
+            "📉 被动监测发现{}流量已持续 {:.0} 分钟卡在约 {:.1} Mbps，开始主动测速确认",
+            plateau.direction, plateau.minutes, plateau.mbps
+        );
+        notify::broadcast(&self.config, &msg).await;
+
+        if trigger.try_send(()).is_err() {
+            info!("已有检测在执行中，本次不再触发");
+        }
+    }
+}
+
+/// 判断一组速率（Mbps）是否构成平台，是则返回平均值
+///
+/// 每个值都不低于 `min_busy_mbps`（空闲不算），平均值低于 `threshold`，最大最小值之差
+/// 不超过平均值的 `tolerance`；设置了 `plateau_mbps` 时平均值还要在它的容差范围内。
+/// 被动监测和云监控指标共用这套规则。
+pub fn plateau_mean(values: &[f64], threshold: f64, settings: &PassiveConfig) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max = values.iter().copied().fold(0.0, f64::max);
+
+    if min < settings.min_busy_mbps || mean >= threshold {
+        return None;
+    }
+    if max - min > settings.tolerance * mean {
+        return None;
+    }
+    if let Some(ceiling) = settings.plateau_mbps {
+        if (mean - ceiling).abs() > settings.tolerance * ceiling {
+            return None;
+        }
+    }
+    Some(mean)
+}
+
+/// 从 /proc/net/dev 读取网卡累计收发字节数：(rx, tx)
+fn read_counters(interface: &str) -> Result<(u64, u64)> {
+    let content = std::fs::read_to_string("/proc/net/dev").context("读取 /proc/net/dev 失败")?;
+    for line in content.lines().skip(2) {
+        let Some((name, data)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim() != interface {
+            continue;
+        }
+        let fields: Vec<&str> = data.split_whitespace().collect();
+        if fields.len() < 9 {
+            anyhow::bail!("网卡 {} 的计数器格式不正确", interface);
+        }
+        let rx = fields[0].parse().context("解析接收字节数失败")?;
+        let tx = fields[8].parse().context("解析发送字节数失败")?;
+        return Ok((rx, tx));
+    }
+    anyhow::bail!("/proc/net/dev 中找不到网卡 {}", interface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (说明, 速率, 阈值, plateau_mbps, 期望的平均值)
+    type Case = (&'static str, &'static [f64], f64, Option<f64>, Option<f64>);
+
+    fn settings(plateau_mbps: Option<f64>) -> PassiveConfig {
+        PassiveConfig {
+            interface: Some("eth0".to_string()),
+            sample_interval_secs: 60,
+            window_minutes: 5,
+            min_busy_mbps: 2.0,
+            tolerance: 0.15,
+            plateau_mbps,
+            cooldown_minutes: 30,
+        }
+    }
+
+    #[test]
+    fn plateau_rules() {
+        let cases: &[Case] = &[
+            ("稳定卡在 10", &[10.0, 10.2, 9.9, 10.1], 20.0, None, Some(10.05)),
+            ("没有数据", &[], 20.0, None, None),
+            ("平均值不低于阈值", &[20.0, 20.5, 19.8], 20.0, None, None),
+            ("有空闲采样", &[10.0, 1.0, 10.0], 20.0, None, None),
+            ("波动超过容差", &[8.0, 12.0, 10.0], 20.0, None, None),
+            ("卡在设置的限速值附近", &[5.0, 5.1, 4.9], 20.0, Some(5.0), Some(5.0)),
+            ("离设置的限速值太远", &[10.0, 10.1, 9.9], 20.0, Some(5.0), None),
+        ];
+        for (name, values, threshold, ceiling, expected) in cases {
+            let mean = plateau_mean(values, *threshold, &settings(*ceiling));
+            match (mean, expected) {
+                (Some(m), Some(e)) => assert!((m - e).abs() < 1e-9, "{}: {} != {}", name, m, e),
+                (m, e) => assert_eq!(m, *e, "{}", name),
+            }
+        }
+    }
+
+    /// (说明, 每分钟的 (rx, tx), 期望的 (方向, 平均值))
+    type WindowCase = (&'static str, &'static [(f64, f64)], Option<(&'static str, f64)>);
+
+    /// 下载阈值 20 Mbps、上传阈值 5 Mbps，按每分钟一个采样填入 (rx, tx)
+    fn monitor(rates: &[(f64, f64)]) -> PassiveMonitor {
+        let mut config = Config::from_file(crate::config::FileConfig {
+            access_key_id: Some("TestAccessKeyId".to_string()),
+            access_key_secret: Some("TestAccessKeySecret".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        config.speed_threshold = 20.0;
+        config.upload_threshold = Some(5.0);
+        config.passive = settings(None);
+
+        let monitor = PassiveMonitor::new(config);
+        let start = chrono::Local::now() - chrono::Duration::minutes(rates.len() as i64);
+        for (i, (rx, tx)) in rates.iter().enumerate() {
+            monitor.push(Rate {
+                time: start + chrono::Duration::minutes(i as i64),
+                rx_mbps: *rx,
+                tx_mbps: *tx,
+            });
+        }
+        monitor
+    }
+
+    #[test]
+    fn detect_plateau_per_direction() {
+        let cases: &[WindowCase] = &[
+            ("下载卡住", &[(10.0, 0.5); 5], Some(("下载", 10.0))),
+            ("窗口未满", &[(10.0, 0.5); 3], None),
+            ("上传卡住", &[(30.0, 3.0); 5], Some(("上传", 3.0))),
+            ("两个方向都正常", &[(30.0, 0.5); 5], None),
+            (
+                "下载忽高忽低",
+                &[(6.0, 0.5), (14.0, 0.5), (8.0, 0.5), (12.0, 0.5), (10.0, 0.5)],
+                None,
+            ),
+            (
+                "空闲采样已滑出窗口",
+                &[(1.0, 0.5), (1.0, 0.5), (10.0, 0.5), (10.0, 0.5), (10.0, 0.5), (10.0, 0.5), (10.0, 0.5), (10.0, 0.5)],
+                Some(("下载", 10.0)),
+            ),
+        ];
+        for (name, rates, expected) in cases {
+            let plateau = monitor(rates).detect_plateau();
+            assert_eq!(
+                plateau.map(|p| (p.direction, p.mbps)),
+                *expected,
+                "{}",
+                name
+            );
+            if let Some(p) = plateau {
+                assert!(p.minutes >= 5.0, "{}: {}", name, p.minutes);
+            }
+        }
+    }
+}
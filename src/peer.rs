@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures_util::StreamExt;
+use tracing::{error, info};
+
+/// 下载未指定大小时返回的字节数
+const DEFAULT_DOWN_BYTES: u64 = 100 * 1024 * 1024;
+/// 单次下载最多返回的字节数
+const MAX_DOWN_BYTES: u64 = 1024 * 1024 * 1024;
+/// 生成数据的块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 对端测速服务状态
+struct PeerState {
+    secret: String,
+    /// 随机内容的数据块，下载时重复发送
+    chunk: Bytes,
+}
+
+/// 对端测速路由：`/__down?bytes=N` 返回 N 字节生成的数据，`/__up` 接收并丢弃上传的数据
+///
+/// 接口与 Cloudflare 测速节点兼容。请求必须带上 `secret` 参数，
+/// 否则任何人都能让本机发出大流量。
+pub fn router(secret: String) -> Router {
+    let chunk: Vec<u8> = (0..CHUNK_SIZE).map(|_| rand::random::<u8>()).collect();
+    let state = Arc::new(PeerState {
+        secret,
+        chunk: Bytes::from(chunk),
+    });
+    Router::new()
+        .route("/__down", get(handle_down))
+        .route("/__up", post(handle_up).layer(DefaultBodyLimit::disable()))
+        .with_state(state)
+}
+
+/// 独立运行对端测速服务（`--serve-speedtest` 模式）
+pub async fn serve(port: u16, secret: String) {
+    let addr = format!("0.0.0.0:{}", port);
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("对端测速服务启动失败: {}", e);
+            return;
+        }
+    };
+    info!("对端测速服务已启动: http://{}/__down, http://{}/__up", addr, addr);
+
+    if let Err(e) = axum::serve(listener, router(secret)).await {
+        error!("对端测速服务异常退出: {}", e);
+    }
+}
+
+impl PeerState {
+    fn verify_secret(&self, params: &HashMap<String, String>) -> bool {
+        params.get("secret") == Some(&self.secret)
+    }
+}
+
+/// 下载测速：按 `bytes` 参数返回生成的数据
+async fn handle_down(
+    State(state): State<Arc<PeerState>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if !state.verify_secret(&params) {
+        return (StatusCode::FORBIDDEN, "鉴权失败").into_response();
+    }
+
+    let bytes = params
+        .get("bytes")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DOWN_BYTES)
+        .min(MAX_DOWN_BYTES);
+    info!("对端测速: 下载 {:.1} MB", bytes as f64 / 1_000_000.0);
+
+    let chunk = state.chunk.clone();
+    let stream = futures_util::stream::unfold(bytes, move |remaining| {
+        let chunk = chunk.clone();
+        async move {
+            if remaining == 0 {
+                return None;
+            }
+            let n = remaining.min(chunk.len() as u64) as usize;
+            Some((
+                Ok::<_, std::convert::Infallible>(chunk.slice(..n)),
+                remaining - n as u64,
+            ))
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, bytes.to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// 上传测速：读完请求体并丢弃，返回收到的字节数
+async fn handle_up(
+    State(state): State<Arc<PeerState>>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Body,
+) -> Response {
+    if !state.verify_secret(&params) {
+        return (StatusCode::FORBIDDEN, "鉴权失败").into_response();
+    }
+
+    let mut received: u64 = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(data) => received += data.len() as u64,
+            // 客户端计时结束后可能直接断开
+            Err(_) => break,
+        }
+    }
+    info!("对端测速: 上传 {:.1} MB", received as f64 / 1_000_000.0);
+
+    received.to_string().into_response()
+}
@@ -0,0 +1,403 @@
+use std::time::{Duration, Instant};
+
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::ProbeConfig;
+use crate::templates;
+
+/// 一组延迟采样的统计
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyStats {
+    /// 总探测次数
+    pub attempts: u32,
+    /// 成功次数
+    pub success: u32,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub p95_ms: f64,
+    /// 同一目标相邻两次成功探测的延迟差的平均值
+    pub jitter_ms: f64,
+    /// 失败比例（0~1）
+    pub loss_ratio: f64,
+}
+
+impl LatencyStats {
+    /// 由每个目标的采样序列计算统计（None 表示该次探测失败）
+    fn from_series(series: &[Vec<Option<f64>>]) -> Option<Self> {
+        let attempts: usize = series.iter().map(|s| s.len()).sum();
+        if attempts == 0 {
+            return None;
+        }
+
+        let mut rtts: Vec<f64> = series.iter().flatten().flatten().copied().collect();
+        let success = rtts.len();
+
+        let mut diffs = Vec::new();
+        for s in series {
+            let ok: Vec<f64> = s.iter().flatten().copied().collect();
+            diffs.extend(ok.windows(2).map(|w| (w[1] - w[0]).abs()));
+        }
+
+        rtts.sort_by(|a, b| a.total_cmp(b));
+        let (min_ms, avg_ms, p95_ms) = if rtts.is_empty() {
+            (0.0, 0.0, 0.0)
+        } else {
+            let idx = ((rtts.len() as f64 * 0.95).ceil() as usize).clamp(1, rtts.len()) - 1;
+            (
+                rtts[0],
+                rtts.iter().sum::<f64>() / rtts.len() as f64,
+                rtts[idx],
+            )
+        };
+        let jitter_ms = if diffs.is_empty() {
+            0.0
+        } else {
+            diffs.iter().sum::<f64>() / diffs.len() as f64
+        };
+
+        Some(Self {
+            attempts: attempts as u32,
+            success: success as u32,
+            min_ms,
+            avg_ms,
+            p95_ms,
+            jitter_ms,
+            loss_ratio: (attempts - success) as f64 / attempts as f64,
+        })
+    }
+
+    /// 单行说明，如 "最小 12 / 平均 35 / P95 80 ms，抖动 9 ms，失败 0%"
+    fn line(&self) -> String {
+        if self.success == 0 {
+            return format!("{} 次全部失败", self.attempts);
+        }
+        format!(
+            "最小 {:.0} / 平均 {:.0} / P95 {:.0} ms，抖动 {:.0} ms，失败 {:.0}%",
+            self.min_ms,
+            self.avg_ms,
+            self.p95_ms,
+            self.jitter_ms,
+            self.loss_ratio * 100.0
+        )
+    }
+}
+
+/// 一次延迟探测的结果
+#[derive(Debug, Clone)]
+pub struct ProbeReport {
+    /// TCP 建连延迟
+    pub tcp: Option<LatencyStats>,
+    /// HTTP 首字节时间
+    pub ttfb: Option<LatencyStats>,
+    /// 超过阈值的项，如 "P95 延迟 350ms > 300ms"
+    pub breaches: Vec<String>,
+}
+
+impl ProbeReport {
+    /// 是否有指标超过阈值
+    pub fn exceeded(&self) -> bool {
+        !self.breaches.is_empty()
+    }
+
+    /// 用于判定和工单描述的主要指标：有 TCP 探测时用 TCP，否则用 HTTP 首字节时间
+    pub fn primary(&self) -> Option<&LatencyStats> {
+        self.tcp.as_ref().or(self.ttfb.as_ref())
+    }
+
+    /// 通知用的详细说明
+    pub fn detail(&self) -> String {
+        let mut text = String::new();
+        if let Some(tcp) = &self.tcp {
+            text.push_str(&format!("\n延迟: {}", tcp.line()));
+        }
+        if let Some(ttfb) = &self.ttfb {
+            text.push_str(&format!("\n首字节: {}", ttfb.line()));
+        }
+        if self.exceeded() {
+            text.push_str(&format!("\n⚠️ 超出阈值: {}", self.breaches.join("；")));
+        }
+        text
+    }
+
+    /// 附在工单描述后的延迟说明
+    pub fn ticket_note(&self) -> String {
+        match self.primary() {
+            Some(s) => templates::latency_note(s.avg_ms, s.p95_ms, s.jitter_ms, s.loss_ratio),
+            None => String::new(),
+        }
+    }
+}
+
+/// 执行一轮延迟探测
+///
+/// 所有目标并发探测，同一目标按 `interval_ms` 间隔探测 `count` 次，单次超过 `timeout_ms` 计为失败。
+pub async fn run(config: &ProbeConfig) -> ProbeReport {
+    info!(
+        "正在探测延迟（{} 个 TCP 目标，{} 个 HTTP 地址，各 {} 次）...",
+        config.targets.len(),
+        config.http_urls.len(),
+        config.count
+    );
+
+    let interval = Duration::from_millis(config.interval_ms);
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let count = config.count.max(1);
+
+    let mut tcp_tasks = JoinSet::new();
+    for target in &config.targets {
+        let target = target.clone();
+        tcp_tasks.spawn(async move { tcp_series(&target, count, interval, timeout).await });
+    }
+
+    // 不复用连接：每次采样都包含建连和 TLS 握手，否则第一次采样会明显慢于后续几次
+    let http = reqwest::Client::builder()
+        .user_agent("Mozilla/5.0")
+        .timeout(timeout)
+        .pool_max_idle_per_host(0)
+        .build();
+    let mut http_tasks = JoinSet::new();
+    match http {
+        Ok(http) => {
+            for url in &config.http_urls {
+                let http = http.clone();
+                let url = url.clone();
+                http_tasks.spawn(async move { ttfb_series(&http, &url, count, interval).await });
+            }
+        }
+        Err(e) => warn!("创建 HTTP 客户端失败，跳过首字节探测: {}", e),
+    }
+
+    let tcp_series: Vec<Vec<Option<f64>>> = tcp_tasks.join_all().await;
+    let http_series: Vec<Vec<Option<f64>>> = http_tasks.join_all().await;
+
+    let mut report = ProbeReport {
+        tcp: LatencyStats::from_series(&tcp_series),
+        ttfb: LatencyStats::from_series(&http_series),
+        breaches: Vec::new(),
+    };
+    report.breaches = breaches(config, report.primary());
+
+    if let Some(s) = report.primary() {
+        info!(
+            "延迟探测完成: 平均 {:.1} ms, P95 {:.1} ms, 抖动 {:.1} ms, 失败 {:.0}%",
+            s.avg_ms,
+            s.p95_ms,
+            s.jitter_ms,
+            s.loss_ratio * 100.0
+        );
+    }
+    if report.exceeded() {
+        warn!("延迟探测超出阈值: {}", report.breaches.join("；"));
+    }
+    report
+}
+
+/// 对比阈值，返回超出的项
+fn breaches(config: &ProbeConfig, stats: Option<&LatencyStats>) -> Vec<String> {
+    let Some(s) = stats else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    if let Some(max) = config.max_loss_ratio {
+        if s.loss_ratio > max {
+            out.push(format!(
+                "失败率 {:.0}% > {:.0}%",
+                s.loss_ratio * 100.0,
+                max * 100.0
+            ));
+        }
+    }
+    // 全部失败时延迟数值没有意义，只看失败率
+    if s.success == 0 {
+        return out;
+    }
+    let checks = [
+        ("平均延迟", s.avg_ms, config.max_avg_ms),
+        ("P95 延迟", s.p95_ms, config.max_p95_ms),
+        ("抖动", s.jitter_ms, config.max_jitter_ms),
+    ];
+    for (name, value, max) in checks {
+        if let Some(max) = max {
+            if value > max {
+                out.push(format!("{} {:.0}ms > {:.0}ms", name, value, max));
+            }
+        }
+    }
+    out
+}
+
+/// 对一个 host:port 反复测 TCP 建连耗时
+///
+/// 域名只在开始时解析一次，之后直接连接解析出的地址，避免把 DNS 查询时间算进建连延迟。
+/// 解析失败时所有探测都计为失败。
+async fn tcp_series(target: &str, count: u32, interval: Duration, timeout: Duration) -> Vec<Option<f64>> {
+    let addr = match tokio::time::timeout(timeout, tokio::net::lookup_host(target)).await {
+        Ok(Ok(mut addrs)) => addrs.next(),
+        Ok(Err(e)) => {
+            warn!("TCP 探测 {} 解析失败: {}", target, e);
+            None
+        }
+        Err(_) => {
+            warn!("TCP 探测 {} 解析超时", target);
+            None
+        }
+    };
+    let Some(addr) = addr else {
+        return vec![None; count as usize];
+    };
+
+    let mut series = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        if i > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        let start = Instant::now();
+        let rtt = match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => Some(start.elapsed().as_secs_f64() * 1000.0),
+            Ok(Err(e)) => {
+                // 同一目标只提示第一次失败
+                if series.iter().all(Option::is_some) {
+                    warn!("TCP 探测 {} 失败: {}", target, e);
+                }
+                None
+            }
+            Err(_) => None,
+        };
+        series.push(rtt);
+    }
+    series
+}
+
+/// 对一个 URL 反复测 HTTP 首字节时间（收到响应头为止）
+async fn ttfb_series(http: &reqwest::Client, url: &str, count: u32, interval: Duration) -> Vec<Option<f64>> {
+    let mut series = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        if i > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        let start = Instant::now();
+        let ttfb = match http.get(url).send().await {
+            Ok(_) => Some(start.elapsed().as_secs_f64() * 1000.0),
+            Err(e) => {
+                if series.iter().all(Option::is_some) {
+                    warn!("HTTP 探测 {} 失败: {}", url, e);
+                }
+                None
+            }
+        };
+        series.push(ttfb);
+    }
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ProbeConfig {
+        ProbeConfig {
+            targets: Vec::new(),
+            http_urls: Vec::new(),
+            count: 3,
+            interval_ms: 10,
+            timeout_ms: 1000,
+            max_avg_ms: Some(100.0),
+            max_p95_ms: Some(200.0),
+            max_jitter_ms: Some(30.0),
+            max_loss_ratio: Some(0.2),
+        }
+    }
+
+    #[test]
+    fn stats_from_series() {
+        assert!(LatencyStats::from_series(&[]).is_none());
+        assert!(LatencyStats::from_series(&[Vec::new()]).is_none());
+
+        // 两个目标共 10 次探测，失败 2 次；抖动只在同一目标的相邻成功探测之间计算
+        let series = vec![
+            vec![Some(10.0), Some(20.0), None, Some(40.0), Some(30.0)],
+            vec![Some(100.0), None, Some(100.0), Some(60.0), Some(20.0)],
+        ];
+        let s = LatencyStats::from_series(&series).unwrap();
+        assert_eq!((s.attempts, s.success), (10, 8));
+        assert_eq!(s.min_ms, 10.0);
+        assert_eq!(s.avg_ms, 47.5);
+        assert_eq!(s.p95_ms, 100.0);
+        // (10 + 20 + 10) + (0 + 40 + 40) 共 6 个差值
+        assert_eq!(s.jitter_ms, 20.0);
+        assert_eq!(s.loss_ratio, 0.2);
+
+        let s = LatencyStats::from_series(&[vec![None, None]]).unwrap();
+        assert_eq!((s.attempts, s.success, s.loss_ratio), (2, 0, 1.0));
+        assert_eq!(s.line(), "2 次全部失败");
+    }
+
+    #[test]
+    fn breaches_against_thresholds() {
+        let stats = |avg_ms: f64, p95_ms: f64, jitter_ms: f64, success: u32| LatencyStats {
+            attempts: 10,
+            success,
+            min_ms: 1.0,
+            avg_ms,
+            p95_ms,
+            jitter_ms,
+            loss_ratio: (10 - success) as f64 / 10.0,
+        };
+        let cases = [
+            ("全部正常", stats(50.0, 150.0, 10.0, 10), vec![]),
+            ("平均延迟超标", stats(150.0, 150.0, 10.0, 10), vec!["平均延迟 150ms > 100ms"]),
+            ("P95 超标", stats(50.0, 250.0, 10.0, 10), vec!["P95 延迟 250ms > 200ms"]),
+            ("抖动超标", stats(50.0, 150.0, 40.0, 10), vec!["抖动 40ms > 30ms"]),
+            ("失败率超标", stats(50.0, 150.0, 10.0, 7), vec!["失败率 30% > 20%"]),
+            ("刚好等于阈值", stats(100.0, 200.0, 30.0, 8), vec![]),
+            (
+                "多项超标",
+                stats(150.0, 250.0, 10.0, 10),
+                vec!["平均延迟 150ms > 100ms", "P95 延迟 250ms > 200ms"],
+            ),
+            // 全部失败时延迟数值没有意义
+            ("全部失败", stats(0.0, 0.0, 0.0, 0), vec!["失败率 100% > 20%"]),
+        ];
+        for (name, s, expected) in cases {
+            assert_eq!(breaches(&config(), Some(&s)), expected, "{}", name);
+        }
+        assert!(breaches(&config(), None).is_empty());
+
+        let unlimited = ProbeConfig {
+            max_avg_ms: None,
+            max_p95_ms: None,
+            max_jitter_ms: None,
+            max_loss_ratio: None,
+            ..config()
+        };
+        assert!(breaches(&unlimited, Some(&stats(999.0, 999.0, 999.0, 0))).is_empty());
+    }
+
+    #[tokio::test]
+    async fn tcp_series_connects_to_the_resolved_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while listener.accept().await.is_ok() {}
+        });
+        let interval = Duration::from_millis(10);
+        let timeout = Duration::from_secs(1);
+
+        let series = tcp_series(&format!("127.0.0.1:{}", port), 3, interval, timeout).await;
+        assert_eq!(series.len(), 3);
+        assert!(series.iter().all(Option::is_some), "{:?}", series);
+
+        // 端口未监听时每次都计为失败
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let series = tcp_series(&format!("127.0.0.1:{}", closed_port), 2, interval, timeout).await;
+        assert_eq!(series, vec![None, None]);
+
+        // 地址格式不对时不再逐次探测
+        let series = tcp_series("no-port", 4, interval, timeout).await;
+        assert_eq!(series, vec![None; 4]);
+    }
+}
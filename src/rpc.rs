@@ -0,0 +1,575 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use tracing::{debug, warn};
+
+use crate::config::{ApiConfig, Config};
+use crate::credentials::CredentialProvider;
+use crate::signer::{AliyunSigner, SignatureAlgorithm};
+
+/// 重试退避的初始间隔，之后每次翻倍
+const RETRY_BASE: Duration = Duration::from_secs(1);
+/// 单次退避的上限
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(20);
+
+/// 可以重试的错误码：限流、服务端临时故障、nonce 冲突、时间戳偏差
+///
+/// 以 "Throttling" 开头的错误码都视为限流。
+const RETRYABLE_CODES: &[&str] = &[
+    "ServiceUnavailable",
+    "InternalError",
+    "UnknownError",
+    "SignatureNonceUsed",
+    "InvalidTimeStamp.Expired",
+    "RequestTimeTooSkewed",
+];
+
+/// 本地时间与服务端偏差过大时返回的错误码
+const CLOCK_SKEW_CODES: &[&str] = &["InvalidTimeStamp.Expired", "RequestTimeTooSkewed"];
+
+/// 常见永久错误的处理建议
+const HINTS: &[(&str, &str)] = &[
+    (
+        "InvalidAccessKeyId.NotFound",
+        "AccessKey ID 不存在，请检查 access_key_id 是否填写正确、该 AccessKey 是否已被删除",
+    ),
+    (
+        "InvalidAccessKeyId.Inactive",
+        "AccessKey 已被禁用，请在 RAM 控制台启用或换一个 AccessKey",
+    ),
+    (
+        "SignatureDoesNotMatch",
+        "签名不匹配，请检查 access_key_secret 是否正确（注意首尾不要带空格）",
+    ),
+    (
+        "IncompleteSignature",
+        "签名不匹配，请检查 access_key_secret 是否正确（注意首尾不要带空格）",
+    ),
+    (
+        "Forbidden.RAM",
+        "RAM 用户没有调用该接口的权限，请按 README 授予对应的系统策略",
+    ),
+    (
+        "NoPermission",
+        "RAM 用户没有调用该接口的权限，请按 README 授予对应的系统策略；AssumeRole 时还要检查角色的信任策略是否允许该账号扮演",
+    ),
+    (
+        "InvalidSecurityToken.Expired",
+        "STS 临时凭证已过期，请更新 security_token，或改用 assume_role 自动刷新",
+    ),
+    (
+        "InvalidSecurityToken.MismatchWithAccessKey",
+        "security_token 与 AccessKey 不是同一组临时凭证，请一起更新",
+    ),
+    (
+        "EntityNotExist.Role",
+        "assume_role.role_arn 对应的角色不存在，请在 RAM 控制台核对角色 ARN",
+    ),
+    (
+        "InvalidTimeStamp.Expired",
+        "本机时间与阿里云相差过大，请同步系统时间（如启用 chrony 或 systemd-timesyncd）",
+    ),
+];
+
+/// 阿里云 RPC 风格 API 的通用客户端（V3 签名，算法由 `api.signature_algorithm` 决定）
+///
+/// 一个实例对应一个 endpoint 和 API 版本，工单、轻量应用服务器、云监控等客户端共用。
+/// 超时、限流、5xx 等临时错误会按指数退避重试，每次重试都重新生成 nonce 和时间戳。
+#[derive(Clone)]
+pub struct AliyunRpcClient {
+    /// 请求协议，默认 https；只有工单客户端会按 api.scheme 改成 http（连接本地模拟服务）
+    scheme: String,
+    endpoint: String,
+    version: String,
+    credentials: Arc<CredentialProvider>,
+    http: reqwest::Client,
+    max_retries: u32,
+    signature_algorithm: SignatureAlgorithm,
+    /// 服务端时间减本地时间（秒），收到时间戳偏差错误后按响应的 Date 头校正
+    clock_offset: Arc<AtomicI64>,
+}
+
+/// 阿里云 API 返回的错误，带上错误码和 RequestId 方便排查
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub action: String,
+    /// HTTP 状态码（HTTP 200 但 Success 为 false 时也会记录 200）
+    pub status: u16,
+    /// 错误码，如 "Throttling.User"、"InvalidAccessKeyId.NotFound"
+    pub code: Option<String>,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 失败: {} (HTTP {}, Code: {}, RequestId: {})",
+            self.action,
+            self.message,
+            self.status,
+            self.code.as_deref().unwrap_or("-"),
+            self.request_id.as_deref().unwrap_or("-")
+        )?;
+        if let Some(hint) = self.hint() {
+            write!(f, "\n💡 {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// 是否属于临时错误，重试可能成功
+    pub fn retryable(&self) -> bool {
+        let code = self.code.as_deref().unwrap_or_default();
+        self.status >= 500
+            || self.status == 429
+            || code.starts_with("Throttling")
+            || RETRYABLE_CODES.contains(&code)
+    }
+
+    /// 是否为服务端内部错误：请求已送达，可能已经部分生效
+    pub fn server_error(&self) -> bool {
+        let code = self.code.as_deref().unwrap_or_default();
+        self.status >= 500
+            || matches!(
+                code,
+                "ServiceUnavailable" | "InternalError" | "UnknownError"
+            )
+    }
+
+    /// 常见错误的处理建议
+    pub fn hint(&self) -> Option<&'static str> {
+        let code = self.code.as_deref()?;
+        HINTS
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, hint)| *hint)
+    }
+
+    fn security_token_invalid(&self) -> bool {
+        self.code
+            .as_deref()
+            .is_some_and(|c| c.starts_with("InvalidSecurityToken"))
+    }
+
+    fn clock_skew(&self) -> bool {
+        self.code
+            .as_deref()
+            .is_some_and(|c| CLOCK_SKEW_CODES.contains(&c))
+    }
+
+    /// 从响应 body 中提取错误信息；body 不是 JSON 时原样作为错误说明
+    ///
+    /// 兼容 `Code` 为字符串或数字两种写法。
+    fn from_body(action: &str, status: u16, body: &str) -> Self {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let field = |name: &str| match &json[name] {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        };
+        Self {
+            action: action.to_string(),
+            status,
+            code: field("Code"),
+            message: field("Message").unwrap_or_else(|| {
+                if json.is_null() {
+                    format!("HTTP {}: {}", status, body)
+                } else {
+                    format!("HTTP {}", status)
+                }
+            }),
+            request_id: field("RequestId"),
+        }
+    }
+}
+
+impl AliyunRpcClient {
+    /// 使用配置中的凭证
+    pub fn new(config: &Config, endpoint: impl Into<String>, version: impl Into<String>) -> Self {
+        Self::with_credentials(&config.api, config.credentials.clone(), endpoint, version)
+    }
+
+    pub fn with_credentials(
+        api: &ApiConfig,
+        credentials: Arc<CredentialProvider>,
+        endpoint: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Self {
+        Self {
+            scheme: "https".to_string(),
+            endpoint: endpoint.into(),
+            version: version.into(),
+            credentials,
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(api.timeout_secs))
+                .build()
+                .unwrap_or_default(),
+            max_retries: api.max_retries,
+            signature_algorithm: api.signature_algorithm,
+            clock_offset: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    /// 换一个 endpoint（如同一产品的其他地域），复用凭证和 HTTP 连接池
+    pub fn with_endpoint(&self, endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            ..self.clone()
+        }
+    }
+
+    /// 换一个请求协议（仅用于工单 API 连接本地模拟服务）
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.scheme = scheme.into();
+        self
+    }
+
+    /// GET 请求，参数全部放在 query 中
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        params: &BTreeMap<String, String>,
+    ) -> Result<T> {
+        self.call("GET", action, params, None).await
+    }
+
+    /// POST 请求，参数以表单形式放在 body 中（适合较长的文本内容）
+    pub async fn post_form<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        params: &BTreeMap<String, String>,
+    ) -> Result<T> {
+        self.call("POST", action, &BTreeMap::new(), Some(params))
+            .await
+    }
+
+    /// 按页码翻页查询（CurrentPage / PageNumber 风格），直到取完 `extract` 报告的总数或遇到空页
+    ///
+    /// `extract` 从每页响应中取出记录和总数（没有总数时以空页结束）。
+    pub async fn get_all_pages<T, I>(
+        &self,
+        action: &str,
+        params: &BTreeMap<String, String>,
+        page_param: &str,
+        size_param: &str,
+        page_size: u32,
+        extract: impl Fn(T) -> Result<(Vec<I>, Option<u64>)>,
+    ) -> Result<Vec<I>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut params = params.clone();
+        params.insert(size_param.to_string(), page_size.to_string());
+        let mut items = Vec::new();
+        for page in 1.. {
+            params.insert(page_param.to_string(), page.to_string());
+            let (batch, total) = extract(self.get(action, &params).await?)?;
+            let empty = batch.is_empty();
+            items.extend(batch);
+            let done = match total {
+                Some(total) => items.len() as u64 >= total,
+                None => false,
+            };
+            if empty || done {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// 按 NextToken 翻页查询，直到 `extract` 不再返回 token
+    pub async fn get_all_by_token<T, I>(
+        &self,
+        action: &str,
+        params: &BTreeMap<String, String>,
+        extract: impl Fn(T) -> Result<(Vec<I>, Option<String>)>,
+    ) -> Result<Vec<I>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut params = params.clone();
+        let mut items = Vec::new();
+        loop {
+            let (batch, token) = extract(self.get(action, &params).await?)?;
+            items.extend(batch);
+            match token.filter(|t| !t.is_empty()) {
+                Some(token) => {
+                    params.insert("NextToken".to_string(), token);
+                }
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+
+    /// 发送请求并解析响应，临时错误按指数退避重试
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        action: &str,
+        query_params: &BTreeMap<String, String>,
+        form_params: Option<&BTreeMap<String, String>>,
+    ) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            let result = match self.send(method, action, query_params, form_params).await {
+                Ok(text) => Self::parse(action, &text),
+                Err(e) => Err(e),
+            };
+            let err = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            // 临时凭证在服务端看来已过期：丢弃缓存，重试时重新获取
+            let token_expired = err
+                .downcast_ref::<ApiError>()
+                .is_some_and(|e| e.security_token_invalid());
+            if token_expired && self.credentials.refreshable() {
+                self.credentials.invalidate().await;
+            }
+            let retry =
+                retryable(&err, action) || (token_expired && self.credentials.refreshable());
+            if attempt >= self.max_retries || !retry {
+                return Err(write_timeout_note(err, action));
+            }
+            attempt += 1;
+            let delay = backoff(attempt);
+            warn!(
+                "{} 调用失败，{:.1} 秒后第 {} 次重试: {:#}",
+                action,
+                delay.as_secs_f64(),
+                attempt,
+                err
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// 当前时间（已按服务端时间校正）
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now() + chrono::Duration::seconds(self.clock_offset.load(Ordering::Relaxed))
+    }
+
+    /// 按响应的 Date 头记录本地与服务端的时间差
+    fn sync_clock(&self, headers: &reqwest::header::HeaderMap) {
+        let server_time = headers
+            .get(reqwest::header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok());
+        if let Some(server_time) = server_time {
+            let offset = server_time.timestamp() - chrono::Utc::now().timestamp();
+            self.clock_offset.store(offset, Ordering::Relaxed);
+            warn!(
+                "本机时间与阿里云相差 {} 秒，之后的请求按服务端时间签名",
+                offset
+            );
+        }
+    }
+
+    /// 解析响应；HTTP 200 但 `Success` 为 false 的也视为错误
+    fn parse<T: DeserializeOwned>(action: &str, text: &str) -> Result<T> {
+        let value: serde_json::Value =
+            serde_json::from_str(text).with_context(|| format!("解析 {} 响应失败", action))?;
+        if value["Success"] == serde_json::Value::Bool(false) {
+            return Err(ApiError::from_body(action, 200, text).into());
+        }
+        serde_json::from_value(value).with_context(|| format!("解析 {} 响应失败", action))
+    }
+
+    async fn send(
+        &self,
+        method: &str,
+        action: &str,
+        query_params: &BTreeMap<String, String>,
+        form_params: Option<&BTreeMap<String, String>>,
+    ) -> Result<String> {
+        let nonce = uuid::Uuid::new_v4().to_string();
+        let timestamp = self.now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        let host = &self.endpoint;
+        let url = format!("{}://{}/", self.scheme, host);
+
+        // 公共头
+        let mut headers = BTreeMap::new();
+        headers.insert("host".to_string(), host.clone());
+        headers.insert("x-acs-action".to_string(), action.to_string());
+        headers.insert("x-acs-version".to_string(), self.version.clone());
+        headers.insert("x-acs-date".to_string(), timestamp);
+        headers.insert("x-acs-signature-nonce".to_string(), nonce);
+
+        // 临时凭证的 SecurityToken 以 x-acs- 开头，会一并参与签名
+        let credentials = self.credentials.get().await?;
+        let mut algorithm = self.signature_algorithm;
+        if let Some(token) = &credentials.security_token {
+            headers.insert("x-acs-security-token".to_string(), token.clone());
+            algorithm = algorithm.for_temporary_credentials();
+        }
+        let signer = AliyunSigner::new(credentials.access_key_id, credentials.access_key_secret)
+            .with_algorithm(algorithm);
+
+        // GET 请求 body 为空；POST 表单需要参与签名的 content-type
+        let body = match form_params {
+            Some(form) => {
+                headers.insert(
+                    "content-type".to_string(),
+                    "application/x-www-form-urlencoded".to_string(),
+                );
+                AliyunSigner::encode_params(form)
+            }
+            None => String::new(),
+        };
+        headers.insert(
+            signer.content_hash_header().to_string(),
+            signer.hash_payload(&body),
+        );
+
+        // 签名
+        let authorization = signer
+            .sign(method, query_params, &headers, &body)
+            .context("签名计算失败")?;
+
+        let method = reqwest::Method::from_bytes(method.as_bytes())?;
+        let mut req = self.http.request(method, &url).query(query_params);
+        for (key, value) in &headers {
+            req = req.header(key.as_str(), value.as_str());
+        }
+
+        debug!("调用 {} {}", host, action);
+        let resp = req
+            .header("Authorization", &authorization)
+            .body(body)
+            .send()
+            .await
+            .context("HTTP 请求失败")?;
+
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let text = resp.text().await.context("读取响应失败")?;
+
+        if !status.is_success() {
+            let err = ApiError::from_body(action, status.as_u16(), &text);
+            if err.clock_skew() {
+                self.sync_clock(&headers);
+            }
+            return Err(err.into());
+        }
+
+        Ok(text)
+    }
+}
+
+/// 只读接口（查询类）重复调用没有副作用
+fn is_read_only(action: &str) -> bool {
+    ["List", "Describe", "Get", "Query"]
+        .iter()
+        .any(|prefix| action.starts_with(prefix))
+}
+
+/// 判断错误是否值得重试
+///
+/// 限流、签名 nonce 冲突等临时错误可以重试（请求被拒绝，没有生效）；
+/// 连接失败时请求还没发出，也可以重试；
+/// 服务端内部错误和超时等请求可能已生效的情况，只有查询类接口才重试，避免重复提交工单。
+fn retryable(err: &anyhow::Error, action: &str) -> bool {
+    if let Some(api_err) = err.downcast_ref::<ApiError>() {
+        return api_err.retryable() && (!api_err.server_error() || is_read_only(action));
+    }
+    match err.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) {
+        Some(e) if e.is_connect() => true,
+        Some(e) if e.is_timeout() || e.is_request() || e.is_body() => is_read_only(action),
+        _ => false,
+    }
+}
+
+/// 写操作超时或服务端出错后不重试，提醒用户到控制台确认是否已经生效
+fn write_timeout_note(err: anyhow::Error, action: &str) -> anyhow::Error {
+    if is_read_only(action) {
+        return err;
+    }
+    let timed_out = err
+        .chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_timeout());
+    let server_error = err
+        .downcast_ref::<ApiError>()
+        .is_some_and(|e| e.server_error());
+    let reason = if timed_out {
+        "请求超时"
+    } else if server_error {
+        "服务端内部错误"
+    } else {
+        return err;
+    };
+    err.context(format!(
+        "{} {}，可能已经生效，为避免重复操作不再重试，请到阿里云控制台确认",
+        action, reason
+    ))
+}
+
+/// 第 `attempt` 次重试前的等待时间：指数退避加随机抖动
+fn backoff(attempt: u32) -> Duration {
+    let exp = RETRY_BASE.saturating_mul(1 << attempt.saturating_sub(1).min(5));
+    let jitter = Duration::from_millis(rand::random::<u64>() % 500);
+    exp.min(RETRY_MAX_DELAY) + jitter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_error(action: &str, status: u16, code: &str) -> anyhow::Error {
+        ApiError {
+            action: action.to_string(),
+            status,
+            code: Some(code.to_string()),
+            message: String::new(),
+            request_id: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn server_errors_retry_only_read_only_actions() {
+        assert!(retryable(
+            &api_error("GetTicket", 503, "ServiceUnavailable"),
+            "GetTicket"
+        ));
+        assert!(!retryable(
+            &api_error("CreateTicket", 503, "ServiceUnavailable"),
+            "CreateTicket"
+        ));
+        assert!(!retryable(
+            &api_error("CreateTicket", 500, "InternalError"),
+            "CreateTicket"
+        ));
+        // 限流时请求没有生效，写操作也可以重试
+        assert!(retryable(
+            &api_error("CreateTicket", 400, "Throttling.User"),
+            "CreateTicket"
+        ));
+        assert!(!retryable(
+            &api_error("CreateTicket", 400, "InvalidParameter"),
+            "CreateTicket"
+        ));
+    }
+
+    #[test]
+    fn write_server_error_is_annotated() {
+        let err = write_timeout_note(
+            api_error("CreateTicket", 500, "InternalError"),
+            "CreateTicket",
+        );
+        assert!(format!("{:#}", err).contains("可能已经生效"));
+        assert!(err.downcast_ref::<ApiError>().is_some());
+    }
+}
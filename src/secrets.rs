@@ -0,0 +1,264 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// 加密值的前缀：`enc:v1:` + base64(12 字节 nonce + AES-256-GCM 密文)
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+/// 未设置 CONFIG_KEY / CONFIG_KEY_FILE 时使用的密钥文件
+const DEFAULT_KEY_FILE: &str = "config.key";
+/// AES-256 密钥长度（字节）
+const KEY_LEN: usize = 32;
+
+/// 读取一项敏感配置
+///
+/// 依次查找：环境变量 `{env_names[0]}_FILE` 指向的文件、环境变量 `env_names`、
+/// config.json 中的值、config.json 中 `{name}_file` 指向的文件。
+/// 同时设置了 `_FILE` 和普通环境变量时以文件为准（与 Docker 镜像的惯例一致）。
+/// 文件内容去掉首尾空白，方便直接使用 systemd credential 或 Docker secret；
+/// 以 `enc:v1:` 开头的值用 [`load_key`] 的密钥解密。
+pub fn resolve(
+    name: &str,
+    env_names: &[&str],
+    value: &Option<String>,
+    file: &Option<String>,
+) -> Result<Option<String>> {
+    let env_value = env_names.iter().find_map(|n| std::env::var(n).ok());
+    let env_file = env_names
+        .first()
+        .and_then(|n| std::env::var(format!("{}_FILE", n)).ok());
+
+    let raw = if let Some(path) = env_file {
+        Some(read_secret_file(name, &path)?)
+    } else if let Some(v) = env_value {
+        Some(v)
+    } else if let Some(v) = value.as_ref().filter(|v| !v.is_empty()) {
+        Some(v.clone())
+    } else if let Some(path) = file {
+        Some(read_secret_file(name, path)?)
+    } else {
+        None
+    };
+
+    raw.map(|v| decrypt_value(name, &v)).transpose()
+}
+
+fn read_secret_file(name: &str, path: &str) -> Result<String> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("读取 {} 的文件 {} 失败", name, path))?;
+    Ok(content.trim().to_string())
+}
+
+/// 解密 `enc:v1:` 开头的值，其他值原样返回
+fn decrypt_value(name: &str, value: &str) -> Result<String> {
+    let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let Some(cipher) = load_key()? else {
+        bail!(
+            "{} 是加密值，但未找到解密密钥：请设置 CONFIG_KEY 或 CONFIG_KEY_FILE，或把密钥放在 {}",
+            name,
+            DEFAULT_KEY_FILE
+        );
+    };
+    decrypt(&cipher, name, encoded)
+}
+
+fn decrypt(cipher: &Aes256Gcm, name: &str, encoded: &str) -> Result<String> {
+    let data = BASE64
+        .decode(encoded.trim())
+        .with_context(|| format!("{} 的加密值格式错误", name))?;
+    if data.len() < 12 {
+        bail!("{} 的加密值格式错误", name);
+    }
+    let (nonce, ciphertext) = data.split_at(12);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("{} 解密失败，密钥与加密时使用的不一致", name))?;
+    String::from_utf8(plaintext).with_context(|| format!("{} 解密结果不是 UTF-8 文本", name))
+}
+
+/// 读取配置加密密钥
+///
+/// 依次查找环境变量 CONFIG_KEY、CONFIG_KEY_FILE 指向的文件和当前目录的 config.key，
+/// 都没有时返回 None。
+fn load_key() -> Result<Option<Aes256Gcm>> {
+    let key = if let Ok(key) = std::env::var("CONFIG_KEY") {
+        key
+    } else if let Ok(path) = std::env::var("CONFIG_KEY_FILE") {
+        std::fs::read_to_string(&path).with_context(|| format!("读取密钥文件 {} 失败", path))?
+    } else {
+        match std::fs::read_to_string(DEFAULT_KEY_FILE) {
+            Ok(key) => key,
+            Err(_) => return Ok(None),
+        }
+    };
+    parse_key(&key).map(Some)
+}
+
+/// 解析配置加密密钥：必须是 base64 编码的 32 字节随机数，直接用作 AES-256 密钥
+///
+/// 不接受口令：口令熵太低，没有加盐的慢哈希时容易被离线穷举。
+fn parse_key(key: &str) -> Result<Aes256Gcm> {
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("配置加密密钥为空");
+    }
+    match BASE64.decode(key) {
+        Ok(bytes) if bytes.len() == KEY_LEN => Ok(Aes256Gcm::new_from_slice(&bytes)?),
+        _ => bail!(
+            "配置加密密钥必须是 base64 编码的 {} 字节随机数（--encrypt 会自动生成，也可以用 openssl rand -base64 32 生成）",
+            KEY_LEN
+        ),
+    }
+}
+
+/// 加密一个配置值，返回可直接写入 config.json 的 `enc:v1:...` 字符串
+fn encrypt_value(cipher: &Aes256Gcm, value: &str) -> Result<String> {
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), value.as_bytes())
+        .map_err(|e| anyhow::anyhow!("加密失败: {}", e))?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(data)))
+}
+
+/// 生成随机密钥并写入 config.key（仅当前用户可读）
+fn generate_key_file() -> Result<Aes256Gcm> {
+    let key = BASE64.encode(rand::random::<[u8; KEY_LEN]>());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(DEFAULT_KEY_FILE)
+        .with_context(|| format!("创建密钥文件 {} 失败", DEFAULT_KEY_FILE))?;
+    writeln!(file, "{}", key)?;
+    eprintln!(
+        "未找到配置加密密钥，已生成随机密钥 {}，请妥善保管，不要与 config.json 放在同一备份中",
+        DEFAULT_KEY_FILE
+    );
+    parse_key(&key)
+}
+
+/// `--encrypt` 模式：从标准输入读取一行明文，输出加密后的配置值
+///
+/// 没有密钥时自动生成 config.key。结果只输出到标准输出，提示信息走标准错误，
+/// 便于在脚本中使用。
+pub fn encrypt_command() -> Result<()> {
+    let cipher = match load_key()? {
+        Some(cipher) => cipher,
+        None => generate_key_file()?,
+    };
+
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprintln!("请输入要加密的值（回车结束）:");
+    }
+    let mut value = String::new();
+    stdin.lock().read_line(&mut value)?;
+    let value = value.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        bail!("没有读取到要加密的值");
+    }
+
+    println!("{}", encrypt_value(&cipher, value)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 写入临时文件，返回路径
+    fn temp_file(content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("aliyun-secret-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn test_cipher() -> Aes256Gcm {
+        parse_key(&BASE64.encode([7u8; KEY_LEN])).unwrap()
+    }
+
+    #[test]
+    fn encrypted_values_round_trip() {
+        let cipher = test_cipher();
+        let encrypted = encrypt_value(&cipher, "ak-secret").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        // 每次加密使用随机 nonce
+        assert_ne!(encrypted, encrypt_value(&cipher, "ak-secret").unwrap());
+
+        let encoded = encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap();
+        assert_eq!(decrypt(&cipher, "test", encoded).unwrap(), "ak-secret");
+    }
+
+    #[test]
+    fn tampered_values_are_rejected() {
+        let cipher = test_cipher();
+        let encrypted = encrypt_value(&cipher, "ak-secret").unwrap();
+        let mut data = BASE64
+            .decode(encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap())
+            .unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let err = decrypt(&cipher, "test", &BASE64.encode(&data)).unwrap_err();
+        assert!(err.to_string().contains("解密失败"));
+
+        let other = parse_key(&BASE64.encode([8u8; KEY_LEN])).unwrap();
+        let encoded = encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap();
+        assert!(decrypt(&other, "test", encoded).is_err());
+        assert!(decrypt(&cipher, "test", "c2hvcnQ=").is_err());
+    }
+
+    #[test]
+    fn keys_must_be_32_random_bytes() {
+        assert!(parse_key(&format!("  {}\n", BASE64.encode([1u8; KEY_LEN]))).is_ok());
+        assert!(parse_key("correct horse battery staple").is_err());
+        assert!(parse_key(&BASE64.encode([1u8; 16])).is_err());
+        assert!(parse_key(" \n").is_err());
+    }
+
+    #[test]
+    fn file_env_takes_precedence_over_plain_env() {
+        // 每个测试使用独立的环境变量名，避免并行测试互相影响
+        std::env::set_var("SECRETS_TEST_PRECEDENCE", "from-env");
+        std::env::set_var("SECRETS_TEST_PRECEDENCE_FILE", temp_file("from-file\n"));
+        let value = resolve(
+            "test",
+            &["SECRETS_TEST_PRECEDENCE"],
+            &Some("from-config".to_string()),
+            &None,
+        )
+        .unwrap();
+        assert_eq!(value.as_deref(), Some("from-file"));
+
+        std::env::remove_var("SECRETS_TEST_PRECEDENCE_FILE");
+        let value = resolve("test", &["SECRETS_TEST_PRECEDENCE"], &None, &None).unwrap();
+        assert_eq!(value.as_deref(), Some("from-env"));
+        std::env::remove_var("SECRETS_TEST_PRECEDENCE");
+    }
+
+    #[test]
+    fn config_values_and_files() {
+        let names = ["SECRETS_TEST_UNSET"];
+        let file = Some(temp_file("  from-file \r\n"));
+
+        // 文件内容去掉首尾空白
+        let value = resolve("test", &names, &None, &file).unwrap();
+        assert_eq!(value.as_deref(), Some("from-file"));
+        // config.json 中的值优先于 _file，空字符串视为未设置
+        let value = resolve("test", &names, &Some("inline".to_string()), &file).unwrap();
+        assert_eq!(value.as_deref(), Some("inline"));
+        let value = resolve("test", &names, &Some(String::new()), &file).unwrap();
+        assert_eq!(value.as_deref(), Some("from-file"));
+
+        assert_eq!(resolve("test", &names, &None, &None).unwrap(), None);
+        let missing = Some("/nonexistent/secret".to_string());
+        assert!(resolve("test", &names, &None, &missing).is_err());
+    }
+}
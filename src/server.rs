@@ -10,7 +10,8 @@ use tracing::{error, info};
 
 use crate::client::WorkorderClient;
 use crate::config::Config;
-use crate::feishu;
+use crate::{feishu, history, peer};
+use crate::tracker::TicketTracker;
 
 /// 待审批的工单请求
 struct PendingApproval {
@@ -23,19 +24,30 @@ pub struct CallbackServer {
     pending: Arc<Mutex<Vec<PendingApproval>>>,
     check_tx: mpsc::Sender<()>,
     secret: Option<String>,
+    data_dir: String,
+    serve_speedtest: bool,
+    tracker: Arc<TicketTracker>,
 }
 
 impl CallbackServer {
-    pub fn new(secret: Option<String>) -> (Self, mpsc::Receiver<()>) {
+    pub fn new(config: &Config, tracker: Arc<TicketTracker>) -> (Self, mpsc::Receiver<()>) {
         let (tx, rx) = mpsc::channel(8);
         let server = Self {
             pending: Arc::new(Mutex::new(Vec::new())),
             check_tx: tx,
-            secret,
+            secret: config.callback_secret.clone(),
+            data_dir: config.data_dir.clone(),
+            serve_speedtest: config.serve_speedtest,
+            tracker,
         };
         (server, rx)
     }
 
+    /// 触发一次完整检测的发送端（供被动监测等内部模块使用）
+    pub fn check_sender(&self) -> mpsc::Sender<()> {
+        self.check_tx.clone()
+    }
+
     /// 验证请求中的 secret
     fn verify_secret(&self, params: &HashMap<String, String>) -> bool {
         match &self.secret {
@@ -76,10 +88,16 @@ impl CallbackServer {
 
     /// 启动 HTTP 服务
     pub async fn start(self: Arc<Self>, port: u16) {
-        let app = Router::new()
+        let mut app = Router::new()
             .route("/approve", get(handle_approve))
             .route("/check", get(handle_check))
+            .route("/history", get(handle_history))
             .with_state(self.clone());
+        // 配置加载时已保证开启对端测速时设置了 callback_secret
+        if let (true, Some(secret)) = (self.serve_speedtest, &self.secret) {
+            app = app.merge(peer::router(secret.clone()));
+            info!("回调服务同时提供对端测速接口: /__down, /__up");
+        }
 
         let addr = format!("0.0.0.0:{}", port);
         info!("回调服务已启动: http://{}", addr);
@@ -118,6 +136,43 @@ async fn handle_check(
     }
 }
 
+/// 转义 HTML 特殊字符，历史记录和错误信息中可能含有外部返回的文本
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// 查看最近的历史记录
+async fn handle_history(
+    State(server): State<Arc<CallbackServer>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Html<String> {
+    if !server.verify_secret(&params) {
+        return Html("<h2>❌ 鉴权失败</h2>".to_string());
+    }
+
+    let records = history::recent(&server.data_dir, 50);
+    if records.is_empty() {
+        return Html("<h2>暂无历史记录</h2>".to_string());
+    }
+
+    let items: String = records
+        .iter()
+        .map(|r| format!("<li>{}</li>", escape_html(&r.summary())))
+        .collect();
+    Html(format!("<h2>📜 最近记录</h2><ul>{}</ul>", items))
+}
+
 async fn handle_approve(
     State(server): State<Arc<CallbackServer>>,
     Query(params): Query<HashMap<String, String>>,
@@ -151,29 +206,80 @@ async fn handle_approve(
     };
 
     info!("收到审批回调，正在提交工单...");
+    history::record_decision(&server.data_dir, "feishu", true);
 
     let client = WorkorderClient::new(config.clone());
-    match client.submit_ticket().await {
-        Ok(ticket_id) => {
-            let msg = format!("工单提交成功，工单号: {}", ticket_id);
+    match client.submit_ticket("feishu").await {
+        Ok(ticket) => {
+            let msg = format!("工单提交成功，工单号: {}", ticket.ticket_id);
             info!("{}", msg);
+            server.tracker.track(&ticket.ticket_id).await;
 
             // 通知飞书
             if let Some(webhook) = &config.feishu_webhook_url {
                 let _ = feishu::send_text(webhook, &format!("✅ {}", msg)).await;
             }
 
-            Html(format!("<h2>✅ {}</h2>", msg))
+            Html(format!("<h2>✅ {}</h2>", escape_html(&msg)))
         }
         Err(e) => {
             let msg = format!("工单提交失败: {:#}", e);
             error!("{}", msg);
 
+            // 提交失败时恢复 token，审批链接可以再次点击重试
+            let mut pending = server.pending.lock().await;
+            if let Some(item) = pending.iter_mut().find(|p| p.token == token) {
+                item.used = false;
+            }
+            drop(pending);
+
             if let Some(webhook) = &config.feishu_webhook_url {
-                let _ = feishu::send_text(webhook, &format!("❌ {}", msg)).await;
+                let text = format!("❌ {}（可重新点击审批链接重试）", msg);
+                let _ = feishu::send_text(webhook, &text).await;
             }
 
-            Html(format!("<h2>❌ {}</h2>", msg))
+            Html(format!("<h2>❌ {}</h2><p>可刷新本页面重试</p>", escape_html(&msg)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FileConfig;
+
+    #[tokio::test]
+    async fn failed_submission_keeps_the_approval_link_usable() {
+        let data_dir =
+            std::env::temp_dir().join(format!("aliyun-auto-ticket-test-{}", uuid::Uuid::new_v4()));
+        let mut config = Config::from_file(FileConfig {
+            access_key_id: Some("TestAccessKeyId".to_string()),
+            access_key_secret: Some("TestAccessKeySecret".to_string()),
+            product_id: Some(1),
+            category_id: Some(1),
+            data_dir: Some(data_dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+        // 指向一个没有监听的端口，提交必然失败
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        config.endpoint = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        config.api.scheme = "http".to_string();
+        config.api.max_retries = 0;
+        config.callback_secret = None;
+
+        let tracker = Arc::new(TicketTracker::new(config.clone()));
+        let (server, _rx) = CallbackServer::new(&config, tracker);
+        let token = server.add_pending(config).await;
+        let server = Arc::new(server);
+
+        let params = HashMap::from([("token".to_string(), token.clone())]);
+        for _ in 0..2 {
+            let Html(page) = handle_approve(State(server.clone()), Query(params.clone())).await;
+            assert!(page.contains("工单提交失败"), "{}", page);
+            let pending = server.pending.lock().await;
+            assert!(pending.iter().any(|p| p.token == token && !p.used));
         }
     }
 }
@@ -0,0 +1,167 @@
+/// 开头丢弃的采样数（TCP 慢启动阶段，每个 100ms 一个）
+const WARMUP_BINS: usize = 10;
+/// 至少需要多少个采样才做判断
+const MIN_BINS: usize = 10;
+/// 与上限相差不超过这个比例的采样视为"贴着上限"
+const CAP_BAND: f64 = 0.1;
+/// 判定为限速所需的最低贴线比例
+const MIN_FLATNESS: f64 = 0.6;
+/// 判定为限速允许的最大变异系数
+const MAX_SHAPED_CV: f64 = 0.3;
+
+/// 吞吐曲线的形态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeKind {
+    /// 平稳地贴着某个上限，典型的令牌桶限速
+    Shaped,
+    /// 低于阈值且忽高忽低，更像是网络拥塞
+    Congested,
+    /// 不低于阈值
+    Normal,
+}
+
+/// 一次测速吞吐曲线的分类结果
+#[derive(Debug, Clone, Copy)]
+pub struct Shape {
+    pub kind: ShapeKind,
+    /// 曲线所贴的上限（Mbps），取 75 分位
+    pub cap_mbps: f64,
+    /// 置信度（0~1）
+    pub confidence: f64,
+}
+
+impl Shape {
+    /// 简短标签，如 "限速在 ~9.8 Mbps"
+    pub fn label(&self) -> String {
+        match self.kind {
+            ShapeKind::Shaped => format!("限速在 ~{:.1} Mbps", self.cap_mbps),
+            ShapeKind::Congested => "拥塞".to_string(),
+            ShapeKind::Normal => "正常".to_string(),
+        }
+    }
+
+    /// 通知用的说明，如 "\n吞吐曲线: 限速在 ~9.8 Mbps（置信度 85%）"
+    pub fn line(&self) -> String {
+        format!(
+            "\n吞吐曲线: {}（置信度 {:.0}%）",
+            self.label(),
+            self.confidence * 100.0
+        )
+    }
+}
+
+/// 根据每 100ms 的吞吐（Mbps）判断曲线形态
+///
+/// 每段曲线先去掉开头的慢启动部分再合并。平均值不低于阈值为正常；否则大部分采样贴着同一个上限、
+/// 波动很小时判定为限速，其余判定为拥塞。采样太少时返回 None。
+pub fn classify(series: &[&[f64]], threshold: f64) -> Option<Shape> {
+    let values: Vec<f64> = series
+        .iter()
+        .flat_map(|s| {
+            if s.len() >= WARMUP_BINS + MIN_BINS {
+                &s[WARMUP_BINS..]
+            } else {
+                s
+            }
+        })
+        .copied()
+        .collect();
+    if values.len() < MIN_BINS {
+        return None;
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if mean <= 0.0 {
+        return None;
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    let cv = variance.sqrt() / mean;
+
+    let mut sorted = values.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let cap = sorted[(sorted.len() * 3 / 4).min(sorted.len() - 1)];
+    let flatness = values
+        .iter()
+        .filter(|v| (**v - cap).abs() <= cap * CAP_BAND)
+        .count() as f64
+        / n;
+
+    if mean >= threshold {
+        return Some(Shape {
+            kind: ShapeKind::Normal,
+            cap_mbps: cap,
+            confidence: 0.5 + (mean / threshold - 1.0).clamp(0.0, 0.5),
+        });
+    }
+
+    if flatness >= MIN_FLATNESS && cv <= MAX_SHAPED_CV {
+        Some(Shape {
+            kind: ShapeKind::Shaped,
+            cap_mbps: cap,
+            confidence: (flatness * (1.0 - cv)).clamp(0.0, 1.0),
+        })
+    } else {
+        Some(Shape {
+            kind: ShapeKind::Congested,
+            cap_mbps: cap,
+            confidence: ((1.0 - flatness) * 0.5 + cv.min(1.0) * 0.5).clamp(0.0, 1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 慢启动阶段逐步爬升，之后按 `steady` 循环取值
+    fn series(steady: &[f64], bins: usize) -> Vec<f64> {
+        let top = steady[0];
+        (0..WARMUP_BINS)
+            .map(|i| top * (i + 1) as f64 / WARMUP_BINS as f64 / 2.0)
+            .chain(steady.iter().copied().cycle().take(bins))
+            .collect()
+    }
+
+    #[test]
+    fn classify_series() {
+        let shaped = series(&[10.0, 9.8, 10.1, 9.9], 40);
+        let congested = series(&[2.0, 15.0, 5.0, 12.0, 8.0], 40);
+        let normal = series(&[30.0, 28.0, 32.0], 40);
+        let idle = [0.0; 30];
+        let short = [10.0; MIN_BINS - 1];
+
+        let cases = vec![
+            ("贴着 10 Mbps", vec![&shaped[..]], Some(ShapeKind::Shaped)),
+            ("多段曲线合并", vec![&shaped[..], &shaped[..]], Some(ShapeKind::Shaped)),
+            ("忽高忽低", vec![&congested[..]], Some(ShapeKind::Congested)),
+            ("不低于阈值", vec![&normal[..]], Some(ShapeKind::Normal)),
+            ("没有流量", vec![&idle[..]], None),
+            ("采样太少", vec![&short[..]], None),
+            ("没有曲线", vec![], None),
+        ];
+        for (name, input, expected) in cases {
+            let shape = classify(&input, 20.0);
+            assert_eq!(shape.map(|s| s.kind), expected, "{}", name);
+            if let Some(s) = shape {
+                assert!((0.0..=1.0).contains(&s.confidence), "{}: {}", name, s.confidence);
+            }
+        }
+
+        let shape = classify(&[&shaped], 20.0).unwrap();
+        // 上限取 75 分位
+        assert_eq!(shape.label(), "限速在 ~10.1 Mbps");
+    }
+
+    #[test]
+    fn warmup_is_discarded() {
+        // 慢启动占一半时长，不去掉的话贴线比例不够
+        let mut ramped: Vec<f64> = (1..=WARMUP_BINS).map(|i| i as f64).collect();
+        ramped.extend(std::iter::repeat_n(10.0, MIN_BINS));
+        assert_eq!(classify(&[&ramped], 20.0).unwrap().kind, ShapeKind::Shaped);
+
+        // 太短的曲线整段保留
+        let short: Vec<f64> = (1..=MIN_BINS).map(|i| i as f64 * 2.0).collect();
+        assert_eq!(classify(&[&short], 20.0).unwrap().kind, ShapeKind::Congested);
+    }
+}
@@ -1,113 +1,882 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
-use tracing::info;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{info, warn};
 
-/// 从收到第一个字节起持续下载的时间（秒）
-const DOWNLOAD_DURATION_SECS: u64 = 10;
-/// 无数据时的超时时间（秒）
-const CONNECT_TIMEOUT_SECS: u64 = 30;
+use crate::config::{Config, SpeedtestBackend, SpeedtestConfig};
+use crate::probe::ProbeReport;
+use crate::shape::{Shape, ShapeKind};
+use crate::templates::TicketContext;
+use crate::{history, iperf3, probe, shape, templates};
 
-/// 测速下载 URL 列表（按优先级排列）
+/// 默认测速下载 URL 列表（按优先级排列）
 /// 使用大文件以充分利用带宽
-const DOWNLOAD_URLS: &[&str] = &[
+pub const DEFAULT_DOWNLOAD_URLS: &[&str] = &[
     // Cloudflare 100MB
     "https://speed.cloudflare.com/__down?bytes=104857600",
     // Cloudflare 25MB（备用）
     "https://speed.cloudflare.com/__down?bytes=26214400",
 ];
 
-/// 执行下载测速，返回下载速度（Mbps）
+/// 默认上传测速地址（Cloudflare）
+pub const DEFAULT_UPLOAD_URL: &str = "https://speed.cloudflare.com/__up";
+/// 上传请求体每块的大小
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// 上传连接在发出任何数据前允许失败的次数
+const UPLOAD_MAX_FAILURES: u32 = 2;
+/// 吞吐曲线的采样间隔
+const SERIES_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 一次测速的结果
+#[derive(Debug, Clone)]
+pub struct SpeedSample {
+    /// 速度（Mbps，所有连接合计）
+    pub mbps: f64,
+    /// 计时窗口内传输的字节数
+    pub bytes: u64,
+    /// 实际计时时长（秒）
+    pub duration_secs: f64,
+    /// 每个连接各自的速度（Mbps），单线程时只有一项
+    pub per_stream: Vec<f64>,
+    /// 每 100ms 的合计吞吐（Mbps）
+    pub series: Vec<f64>,
+}
+
+/// 所有连接共用的计时窗口与字节计数
+struct Progress {
+    /// 第一个字节传输的时刻（计时起点）
+    start: OnceLock<Instant>,
+    /// 所有连接累计传输的字节数，用于采样吞吐曲线
+    bytes: AtomicU64,
+}
+
+impl SpeedSample {
+    /// 多线程测速时的分连接说明，如 "\n4 路并发，单路: 9.5 / 9.6 / 9.4 / 9.7 Mbps"；单线程返回空字符串
+    pub fn streams_line(&self) -> String {
+        if self.per_stream.len() <= 1 {
+            return String::new();
+        }
+        let list: Vec<String> = self.per_stream.iter().map(|s| format!("{:.1}", s)).collect();
+        format!(
+            "\n{} 路并发，单路: {} Mbps",
+            self.per_stream.len(),
+            list.join(" / ")
+        )
+    }
+}
+
+/// 执行下载测速
+///
+/// 同时打开 `streams` 个下载连接，从任一连接收到第一个字节开始计时 `duration_secs` 秒，
+/// 统计窗口内所有连接收到的字节数。如果 `timeout_secs` 秒内未收到任何数据则超时。
+/// 测速方式为 iperf3 时改为反向模式，由 iperf3 服务端发送数据。
+pub async fn measure_download_speed(settings: &SpeedtestConfig) -> Result<SpeedSample> {
+    if settings.backend == SpeedtestBackend::Iperf3 {
+        return measure_iperf3(settings, true).await;
+    }
+    let http = build_client(settings)?;
+    let overall_start = Instant::now();
+    let shared = Arc::new(settings.clone());
+    run_parallel("下载", settings, move |idx, progress| {
+        let http = http.clone();
+        let settings = shared.clone();
+        async move { download_stream(&http, &settings, idx, &progress, overall_start).await }
+    })
+    .await
+}
+
+/// 执行上传测速
+///
+/// 同时打开 `streams` 个连接向 `upload_url` POST 生成的数据，从开始发送数据起计时
+/// `duration_secs` 秒，统计窗口内所有连接发出的字节数。如果 `timeout_secs` 秒内未能开始发送则超时。
+/// 测速方式为 iperf3 时改为向 iperf3 服务端发送数据。
+pub async fn measure_upload_speed(settings: &SpeedtestConfig) -> Result<SpeedSample> {
+    if settings.backend == SpeedtestBackend::Iperf3 {
+        return measure_iperf3(settings, false).await;
+    }
+    let http = build_client(settings)?;
+    let overall_start = Instant::now();
+    // 随机内容，避免链路上的压缩影响结果
+    let chunk: Arc<Vec<u8>> = Arc::new((0..UPLOAD_CHUNK_SIZE).map(|_| rand::random::<u8>()).collect());
+    let shared = Arc::new(settings.clone());
+    run_parallel("上传", settings, move |_idx, progress| {
+        let http = http.clone();
+        let settings = shared.clone();
+        let chunk = chunk.clone();
+        async move { upload_stream(&http, &settings, chunk, progress, overall_start).await }
+    })
+    .await
+}
+
+fn build_client(settings: &SpeedtestConfig) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(settings.user_agent.as_str())
+        .connect_timeout(Duration::from_secs(10));
+    if let Some(ip) = settings.source_ip {
+        builder = builder.local_address(ip);
+    }
+    if let Some(interface) = &settings.bind_interface {
+        builder = bind_interface(builder, interface)?;
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn bind_interface(builder: reqwest::ClientBuilder, interface: &str) -> Result<reqwest::ClientBuilder> {
+    Ok(builder.interface(interface))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn bind_interface(_builder: reqwest::ClientBuilder, interface: &str) -> Result<reqwest::ClientBuilder> {
+    anyhow::bail!("当前系统不支持绑定网卡 {}，请改用 source_ip", interface)
+}
+
+/// 并发运行 `streams` 个测速连接，共用一个计时窗口，汇总为一次测速结果
 ///
-/// 单线程下载，从收到第一个字节开始计时 10 秒。
-/// 如果 30 秒内未收到任何数据则超时。
-pub async fn measure_download_speed() -> Result<f64> {
-    let http = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0")
-        .connect_timeout(Duration::from_secs(10))
-        .build()?;
+/// `worker` 返回该连接在窗口内传输的字节数。窗口内每 100ms 记录一次所有连接的合计吞吐。
+async fn run_parallel<F, Fut>(
+    label: &str,
+    settings: &SpeedtestConfig,
+    worker: F,
+) -> Result<SpeedSample>
+where
+    F: Fn(usize, Arc<Progress>) -> Fut,
+    Fut: Future<Output = Result<u64>> + Send + 'static,
+{
+    let streams = settings.streams.max(1);
+    if streams == 1 {
+        info!("正在进行{}测速（单线程，{}秒）...", label, settings.duration_secs);
+    } else {
+        info!(
+            "正在进行{}测速（{} 线程，{}秒）...",
+            label, streams, settings.duration_secs
+        );
+    }
+
+    let progress = Arc::new(Progress {
+        start: OnceLock::new(),
+        bytes: AtomicU64::new(0),
+    });
+
+    let series_bytes: Arc<std::sync::Mutex<Vec<u64>>> = Arc::default();
+    let sampler = {
+        let progress = progress.clone();
+        let series_bytes = series_bytes.clone();
+        let window = Duration::from_secs(settings.duration_secs);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SERIES_INTERVAL);
+            let mut last = 0u64;
+            loop {
+                ticker.tick().await;
+                let Some(start) = progress.start.get() else {
+                    continue;
+                };
+                // 窗口结束后各连接陆续收尾，这段不计入曲线
+                if start.elapsed() > window {
+                    break;
+                }
+                let total = progress.bytes.load(Ordering::Relaxed);
+                series_bytes.lock().unwrap().push(total - last);
+                last = total;
+            }
+        })
+    };
+
+    let mut tasks = JoinSet::new();
+    for idx in 0..streams {
+        let fut = worker(idx, progress.clone());
+        tasks.spawn(async move { (idx, fut.await) });
+    }
+
+    let mut per_stream_bytes = vec![0u64; streams];
+    let mut last_error = None;
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((idx, Ok(bytes))) => per_stream_bytes[idx] = bytes,
+            Ok((idx, Err(e))) => {
+                warn!("第 {} 路{}失败: {:#}", idx + 1, label, e);
+                last_error = Some(e);
+            }
+            Err(e) => warn!("{}任务异常退出: {}", label, e),
+        }
+    }
+
+    sampler.abort();
+
+    let elapsed = progress
+        .start
+        .get()
+        .map(|s| s.elapsed().as_secs_f64())
+        .unwrap_or(0.0);
+    let total_bytes: u64 = per_stream_bytes.iter().sum();
+
+    if total_bytes == 0 || elapsed == 0.0 {
+        if let Some(e) = last_error {
+            return Err(e);
+        }
+        anyhow::bail!("{}测速失败：未传输任何数据", label);
+    }
+
+    let to_mbps = |bytes: u64| (bytes as f64 * 8.0) / (elapsed * 1_000_000.0);
+    let speed_mbps = to_mbps(total_bytes);
+    let per_stream: Vec<f64> = per_stream_bytes.iter().map(|b| to_mbps(*b)).collect();
+    let bin_secs = SERIES_INTERVAL.as_secs_f64();
+    let series: Vec<f64> = series_bytes
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|b| (*b as f64 * 8.0) / (bin_secs * 1_000_000.0))
+        .collect();
 
     info!(
-        "正在进行下载测速（单线程，{}秒）...",
-        DOWNLOAD_DURATION_SECS
+        "{}测速完成: {:.2} MB, 耗时 {:.1}s, 速度 {:.2} Mbps",
+        label,
+        total_bytes as f64 / 1_000_000.0,
+        elapsed,
+        speed_mbps
     );
 
+    Ok(SpeedSample {
+        mbps: speed_mbps,
+        bytes: total_bytes,
+        duration_secs: elapsed,
+        per_stream,
+        series,
+    })
+}
+
+/// 单个下载连接：持续下载直到共享的计时窗口结束，返回收到的字节数
+///
+/// 第一个收到数据的连接负责开始计时，其余连接共用同一个窗口。
+async fn download_stream(
+    http: &reqwest::Client,
+    settings: &SpeedtestConfig,
+    idx: usize,
+    progress: &Progress,
+    overall_start: Instant,
+) -> Result<u64> {
+    let measure_start = &progress.start;
+    let urls = &settings.download_urls;
+    let window = Duration::from_secs(settings.duration_secs);
+    let timeout = Duration::from_secs(settings.timeout_secs);
     let mut total_bytes: u64 = 0;
-    let mut measure_start: Option<Instant> = None;
-    let overall_start = Instant::now();
-    let mut url_idx = 0;
+    // 不同连接从不同的 URL 开始，分散到不同文件
+    let mut url_idx = idx;
+    let mut attempts = 0;
 
     'outer: loop {
-        // 超时检查：30 秒内没收到任何数据
-        if measure_start.is_none()
-            && overall_start.elapsed() > Duration::from_secs(CONNECT_TIMEOUT_SECS)
-        {
-            anyhow::bail!("下载测速超时：{}秒内未收到任何数据", CONNECT_TIMEOUT_SECS);
+        // 超时检查：timeout_secs 秒内没收到任何数据
+        if measure_start.get().is_none() && overall_start.elapsed() > timeout {
+            anyhow::bail!("下载测速超时：{}秒内未收到任何数据", settings.timeout_secs);
         }
 
-        // 已开始计时，检查是否到 10 秒
-        if let Some(start) = measure_start {
-            if start.elapsed() >= Duration::from_secs(DOWNLOAD_DURATION_SECS) {
+        // 已开始计时，检查窗口是否结束
+        if let Some(start) = measure_start.get() {
+            if start.elapsed() >= window {
                 break;
             }
         }
 
-        let url = DOWNLOAD_URLS[url_idx % DOWNLOAD_URLS.len()];
+        let url = &urls[url_idx % urls.len()];
         // 加随机参数避免缓存
-        let url = format!("{}&t={}", url, chrono::Utc::now().timestamp_millis());
+        let sep = if url.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}t={}", url, sep, chrono::Utc::now().timestamp_millis());
         url_idx += 1;
+        attempts += 1;
 
-        let resp = match http.get(&url).send().await {
+        let resp = match http.get(&url).send().await.and_then(|r| r.error_for_status()) {
             Ok(r) => r,
             Err(e) => {
-                if url_idx <= DOWNLOAD_URLS.len() {
+                if attempts <= urls.len() {
                     // 第一轮各 URL 都尝试一下
                     continue;
                 }
-                anyhow::bail!("测速连接失败: {}", e);
+                // 错误信息不带 URL，对端测速地址里有 secret 参数；
+                // 非 2xx 的响应体（如对端的"鉴权失败"）不能计入吞吐量
+                let hint = status_hint(e.status());
+                anyhow::bail!("测速连接失败: {}{}", e.without_url(), hint);
             }
         };
 
         let mut stream = resp;
         loop {
             // 每个 chunk 前检查是否该停了
-            if let Some(start) = measure_start {
-                if start.elapsed() >= Duration::from_secs(DOWNLOAD_DURATION_SECS) {
+            if let Some(start) = measure_start.get() {
+                if start.elapsed() >= window {
                     break 'outer;
                 }
-            } else if overall_start.elapsed() > Duration::from_secs(CONNECT_TIMEOUT_SECS) {
-                anyhow::bail!("下载测速超时：{}秒内未收到任何数据", CONNECT_TIMEOUT_SECS);
+            } else if overall_start.elapsed() > timeout {
+                anyhow::bail!("下载测速超时：{}秒内未收到任何数据", settings.timeout_secs);
             }
 
             match stream.chunk().await {
                 Ok(Some(chunk)) => {
-                    // 收到第一个字节时开始计时
-                    if measure_start.is_none() {
-                        measure_start = Some(Instant::now());
+                    // 任一连接收到第一个字节时开始计时
+                    if measure_start.set(Instant::now()).is_ok() {
                         info!("数据开始流入，计时开始");
                     }
                     total_bytes += chunk.len() as u64;
+                    progress.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
                 }
                 _ => break,
             }
         }
     }
 
-    let elapsed = measure_start
-        .map(|s| s.elapsed().as_secs_f64())
-        .unwrap_or(0.0);
+    Ok(total_bytes)
+}
 
-    if total_bytes == 0 || elapsed == 0.0 {
-        anyhow::bail!("下载测速失败：未收到任何数据");
+/// 单个上传连接：持续 POST 生成的数据直到共享的计时窗口结束，返回发出的字节数
+///
+/// 请求体按块生成，每被读取一块计一次数；窗口结束后请求体结束，服务端提前断开则重新发起。
+async fn upload_stream(
+    http: &reqwest::Client,
+    settings: &SpeedtestConfig,
+    chunk: Arc<Vec<u8>>,
+    progress: Arc<Progress>,
+    overall_start: Instant,
+) -> Result<u64> {
+    let counter = Arc::new(AtomicU64::new(0));
+    let window = Duration::from_secs(settings.duration_secs);
+    let timeout = Duration::from_secs(settings.timeout_secs);
+    let mut failures = 0;
+
+    loop {
+        if let Some(start) = progress.start.get() {
+            if start.elapsed() >= window {
+                break;
+            }
+        } else if overall_start.elapsed() > timeout {
+            anyhow::bail!("上传测速超时：{}秒内未能开始发送数据", settings.timeout_secs);
+        }
+
+        let body = futures_util::stream::unfold(
+            (progress.clone(), counter.clone(), chunk.clone()),
+            move |(progress, counter, chunk)| async move {
+                // 第一块数据被读取时开始计时
+                let begin = *progress.start.get_or_init(|| {
+                    info!("数据开始流出，计时开始");
+                    Instant::now()
+                });
+                if begin.elapsed() >= window {
+                    return None;
+                }
+                counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                progress.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                let data = chunk.to_vec();
+                Some((Ok::<_, std::io::Error>(data), (progress, counter, chunk)))
+            },
+        );
+
+        let send = http
+            .post(&settings.upload_url)
+            .header("Content-Type", "application/octet-stream")
+            .body(reqwest::Body::wrap_stream(body))
+            .send();
+        match tokio::time::timeout(window + timeout, send).await {
+            Ok(Ok(resp)) => {
+                if let Err(e) = resp.error_for_status() {
+                    let hint = status_hint(e.status());
+                    anyhow::bail!("上传测速失败: {}{}", e.without_url(), hint);
+                }
+            }
+            Ok(Err(e)) => {
+                failures += 1;
+                if counter.load(Ordering::Relaxed) == 0 && failures > UPLOAD_MAX_FAILURES {
+                    anyhow::bail!("上传测速连接失败: {}", e.without_url());
+                }
+            }
+            Err(_) => anyhow::bail!("上传测速超时"),
+        }
     }
 
-    let speed_mbps = (total_bytes as f64 * 8.0) / (elapsed * 1_000_000.0);
+    Ok(counter.load(Ordering::Relaxed))
+}
 
-    info!(
-        "测速完成: 下载 {:.2} MB, 耗时 {:.1}s, 速度 {:.2} Mbps",
-        total_bytes as f64 / 1_000_000.0,
-        elapsed,
-        speed_mbps
+/// 测速地址返回 401 / 403 时提示检查对端测速密钥
+fn status_hint(status: Option<reqwest::StatusCode>) -> &'static str {
+    match status {
+        Some(reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => {
+            "，使用对端测速时请检查 speedtest.peer_secret 是否与对端的 callback_secret 一致"
+        }
+        _ => "",
+    }
+}
+
+/// 通过 iperf3 服务端测速：`reverse` 为 true 时服务端发送（测下载），否则本机发送（测上传）
+///
+/// 控制连接完成协商后，各数据连接共用同一个计时窗口，计时结束再与服务端交换结果。
+async fn measure_iperf3(settings: &SpeedtestConfig, reverse: bool) -> Result<SpeedSample> {
+    let label = if reverse { "下载" } else { "上传" };
+    let (session, streams) = iperf3::Session::start(settings, reverse).await?;
+    let overall_start = Instant::now();
+
+    let count = streams.len();
+    let slots: Arc<Vec<std::sync::Mutex<Option<TcpStream>>>> = Arc::new(
+        streams
+            .into_iter()
+            .map(|s| std::sync::Mutex::new(Some(s)))
+            .collect(),
     );
+    let stream_bytes: Arc<Vec<AtomicU64>> = Arc::new((0..count).map(|_| AtomicU64::new(0)).collect());
+    let drains: Arc<std::sync::Mutex<Vec<JoinHandle<()>>>> = Arc::default();
+    let block: Arc<Vec<u8>> = Arc::new((0..iperf3::BLOCK_SIZE).map(|_| rand::random::<u8>()).collect());
+    let shared = Arc::new(settings.clone());
+
+    let result = {
+        let stream_bytes = stream_bytes.clone();
+        let drains = drains.clone();
+        run_parallel(label, settings, move |idx, progress| {
+            let settings = shared.clone();
+            let slots = slots.clone();
+            let stream_bytes = stream_bytes.clone();
+            let drains = drains.clone();
+            let block = block.clone();
+            async move {
+                let stream = slots[idx]
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .context("iperf3 数据连接数与并发数不一致")?;
+                let bytes =
+                    iperf3_stream(stream, &settings, reverse, &block, &progress, overall_start, &drains)
+                        .await?;
+                stream_bytes[idx].store(bytes, Ordering::Relaxed);
+                Ok(bytes)
+            }
+        })
+        .await
+    };
+
+    if let Ok(sample) = &result {
+        let bytes: Vec<u64> = stream_bytes.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        if let Err(e) = session.finish(&bytes, sample.duration_secs).await {
+            warn!("iperf3 结束测试失败（不影响测速结果）: {:#}", e);
+        }
+    }
+    for drain in drains.lock().unwrap().drain(..) {
+        drain.abort();
+    }
+    result
+}
+
+/// 单个 iperf3 数据连接：读或写直到共享的计时窗口结束，返回窗口内传输的字节数
+///
+/// 窗口结束后连接交给后台任务继续读到服务端关闭为止，避免收尾前断开导致服务端报错。
+async fn iperf3_stream(
+    mut stream: TcpStream,
+    settings: &SpeedtestConfig,
+    reverse: bool,
+    block: &[u8],
+    progress: &Progress,
+    overall_start: Instant,
+    drains: &std::sync::Mutex<Vec<JoinHandle<()>>>,
+) -> Result<u64> {
+    let window = Duration::from_secs(settings.duration_secs);
+    let timeout = Duration::from_secs(settings.timeout_secs);
+    let mut buf = vec![0u8; iperf3::BLOCK_SIZE];
+    let mut total_bytes: u64 = 0;
+
+    loop {
+        let remaining = match progress.start.get() {
+            Some(start) if start.elapsed() >= window => break,
+            Some(start) => window.saturating_sub(start.elapsed()),
+            None if overall_start.elapsed() > timeout => {
+                anyhow::bail!("iperf3 测速超时：{}秒内未传输任何数据", settings.timeout_secs)
+            }
+            None => timeout.saturating_sub(overall_start.elapsed()),
+        };
 
-    Ok(speed_mbps)
+        let io = async {
+            if reverse {
+                stream.read(&mut buf).await
+            } else {
+                stream.write(block).await
+            }
+        };
+        let n = match tokio::time::timeout(remaining, io).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => return Err(e).context("iperf3 数据连接中断"),
+            // 回到循环开头检查窗口或超时
+            Err(_) => continue,
+        };
+
+        // 任一连接传输第一块数据时开始计时
+        if progress.start.set(Instant::now()).is_ok() {
+            info!("数据开始{}，计时开始", if reverse { "流入" } else { "流出" });
+        }
+        total_bytes += n as u64;
+        progress.bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    drains.lock().unwrap().push(tokio::spawn(async move {
+        let mut buf = vec![0u8; iperf3::BLOCK_SIZE];
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    }));
+
+    Ok(total_bytes)
+}
+
+/// 测速方向
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Download,
+    Upload,
+}
+
+impl Direction {
+    fn label(self) -> &'static str {
+        match self {
+            Direction::Download => "下载",
+            Direction::Upload => "上传",
+        }
+    }
+
+    async fn measure(self, config: &Config) -> Result<SpeedSample> {
+        match self {
+            Direction::Download => measure_download_speed(&config.speedtest).await,
+            Direction::Upload => measure_upload_speed(&config.speedtest).await,
+        }
+    }
+
+    fn record(self, data_dir: &str, samples: &[SpeedSample], mbps: f64) {
+        match self {
+            Direction::Download => history::record_speed_check(data_dir, samples, mbps),
+            Direction::Upload => history::record_upload_check(data_dir, samples, mbps),
+        }
+    }
+}
+
+/// 上传方向的判定结果
+#[derive(Debug, Clone)]
+pub struct UploadVerdict {
+    /// 所有采样（第一次 + 复测）
+    pub samples: Vec<SpeedSample>,
+    /// 代表速度（所有采样的中位数）
+    pub mbps: f64,
+    /// 上传阈值（Mbps）
+    pub threshold: f64,
+    /// 上传是否判定为限速
+    pub throttled: bool,
+}
+
+impl UploadVerdict {
+    fn speeds(&self) -> Vec<f64> {
+        self.samples.iter().map(|s| s.mbps).collect()
+    }
+}
+
+/// 带复测确认的限速判定结果
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    /// 所有下载采样（第一次 + 复测）
+    pub samples: Vec<SpeedSample>,
+    /// 代表下载速度（所有采样的中位数）
+    pub mbps: f64,
+    /// 下载是否判定为限速
+    pub download_throttled: bool,
+    /// 下载吞吐曲线的形态
+    pub shape: Option<Shape>,
+    /// 下载低于阈值，但曲线判定为拥塞而不视为限速
+    pub congested: bool,
+    /// 上传测速结果（配置了 upload_threshold 时才有）
+    pub upload: Option<UploadVerdict>,
+    /// 延迟探测结果（配置了 probe 目标时才有）
+    pub probe: Option<ProbeReport>,
+    /// 是否判定为限速（下载、上传或延迟任一异常）
+    pub throttled: bool,
+}
+
+impl Verdict {
+    /// 所有下载采样的速度（Mbps）
+    pub fn speeds(&self) -> Vec<f64> {
+        self.samples.iter().map(|s| s.mbps).collect()
+    }
+
+    /// 首次下载采样（多线程时用于展示分连接速度）
+    pub fn first(&self) -> &SpeedSample {
+        &self.samples[0]
+    }
+
+    /// 上传是否判定为限速
+    pub fn upload_throttled(&self) -> bool {
+        self.upload.as_ref().is_some_and(|u| u.throttled)
+    }
+
+    /// 延迟探测是否超过阈值
+    pub fn probe_exceeded(&self) -> bool {
+        self.probe.as_ref().is_some_and(|p| p.exceeded())
+    }
+
+    /// 曲线判定为限速时的上限值（Mbps）
+    pub fn shaped_cap(&self) -> Option<f64> {
+        self.shape
+            .filter(|s| s.kind == ShapeKind::Shaped)
+            .map(|s| s.cap_mbps)
+    }
+
+    /// 告警原因，如 "下载限速"、"上传限速、延迟异常"
+    pub fn alert_reasons(&self) -> String {
+        let mut reasons = Vec::new();
+        if self.download_throttled {
+            reasons.push("下载限速");
+        }
+        if self.upload_throttled() {
+            reasons.push("上传限速");
+        }
+        if self.probe_exceeded() {
+            reasons.push("延迟异常");
+        }
+        if reasons.is_empty() {
+            reasons.push("下载限速");
+        }
+        reasons.join("、")
+    }
+
+    /// 生成工单描述：按异常类型选择模板，附上复测结果、多线程分连接结果和延迟数据
+    pub fn ticket_description(&self, ctx: &TicketContext) -> String {
+        let mut description = if self.download_throttled {
+            let mut d = format!(
+                "{}{}",
+                templates::random_description(ctx, self.mbps, &self.speeds()),
+                templates::streams_note(&self.first().per_stream)
+            );
+            if let Some(cap) = self.shaped_cap() {
+                d.push_str(&templates::cap_note(cap));
+            }
+            if let Some(up) = self.upload.as_ref().filter(|u| u.throttled) {
+                d.push_str(&templates::upload_note(up.mbps));
+            }
+            d
+        } else if let Some(up) = self.upload.as_ref().filter(|u| u.throttled) {
+            templates::random_upload_description(ctx, up.mbps, &up.speeds())
+        } else if let Some(s) = self.probe.as_ref().filter(|p| p.exceeded()).and_then(|p| p.primary()) {
+            // 只有延迟异常，延迟数据已在正文里
+            return templates::random_latency_description(ctx, s.avg_ms, s.p95_ms, s.jitter_ms, s.loss_ratio);
+        } else {
+            templates::random_description(ctx, self.mbps, &self.speeds())
+        };
+        if let Some(p) = &self.probe {
+            description.push_str(&p.ticket_note());
+        }
+        description
+    }
+
+    /// 通知用的详细说明：复测采样 + 多线程分连接结果 + 吞吐曲线 + 上传结果 + 延迟探测
+    pub fn detail(&self) -> String {
+        let upload = match &self.upload {
+            Some(up) => format!(
+                "\n上传速度: {:.2} Mbps（阈值: {} Mbps）{}{}",
+                up.mbps,
+                up.threshold,
+                if up.throttled { " ⚠️" } else { "" },
+                samples_line(&up.samples, up.mbps)
+            ),
+            None => String::new(),
+        };
+        let probe = self.probe.as_ref().map(|p| p.detail()).unwrap_or_default();
+        let shape = self.shape.map(|s| s.line()).unwrap_or_default();
+        format!(
+            "{}{}{}{}{}",
+            samples_line(&self.samples, self.mbps),
+            self.first().streams_line(),
+            shape,
+            upload,
+            probe
+        )
+    }
+}
+
+/// 多次采样时的说明文字，如 "\n采样: 8.2 / 9.1 / 8.7 Mbps（中位数 8.7）"；单次采样返回空字符串
+fn samples_line(samples: &[SpeedSample], median: f64) -> String {
+    if samples.len() <= 1 {
+        return String::new();
+    }
+    let list: Vec<String> = samples.iter().map(|s| format!("{:.1}", s.mbps)).collect();
+    format!("\n采样: {} Mbps（中位数 {:.1}）", list.join(" / "), median)
+}
+
+/// 中位数（偶数个时取中间两个的平均值）
+pub fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// 测速并判定是否限速
+///
+/// 第一次采样低于阈值时，每隔 `confirm_interval_secs` 秒复测一次，共复测
+/// `confirm_samples` 次。配置了 `confirm_quorum` 时，至少有这么多次采样低于阈值才判定为限速；
+/// 否则以所有采样的中位数是否低于阈值为准。每个方向只写一条历史记录（速度为中位数）。
+///
+/// 配置了 `upload_threshold` 时，下载之后再按同样的规则测上传，任一方向限速即判定为限速。
+/// 上传测速失败只打日志，不影响下载的判定。
+///
+/// 下载低于阈值时再看吞吐曲线：判定为拥塞且置信度达到 `congestion_confidence` 时不视为限速。
+///
+/// 配置了延迟探测目标时，先在测速前探测延迟（避免测速占满带宽影响延迟），超过阈值同样触发告警。
+pub async fn check_throttle(config: &Config) -> Result<Verdict> {
+    let probe = if config.probe.enabled() {
+        let report = probe::run(&config.probe).await;
+        history::record_probe(&config.data_dir, &report);
+        Some(report)
+    } else {
+        None
+    };
+
+    let (samples, mbps, mut download_throttled) =
+        sample_confirmed(config, Direction::Download, config.speed_threshold).await?;
+
+    // 低于阈值但曲线忽高忽低，更像是拥塞，不当作限速
+    let series: Vec<&[f64]> = samples.iter().map(|s| s.series.as_slice()).collect();
+    let shape = shape::classify(&series, config.speed_threshold);
+    let congested = download_throttled
+        && shape.is_some_and(|s| {
+            s.kind == ShapeKind::Congested && s.confidence >= config.congestion_confidence
+        });
+    if congested {
+        info!("下载速度低于阈值，但吞吐曲线波动明显，判定为拥塞");
+        download_throttled = false;
+    }
+
+    let upload = match config.upload_threshold {
+        Some(threshold) => match sample_confirmed(config, Direction::Upload, threshold).await {
+            Ok((samples, mbps, throttled)) => Some(UploadVerdict {
+                samples,
+                mbps,
+                threshold,
+                throttled,
+            }),
+            Err(e) => {
+                warn!("上传测速失败: {:#}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let throttled = download_throttled
+        || upload.as_ref().is_some_and(|u| u.throttled)
+        || probe.as_ref().is_some_and(|p| p.exceeded());
+
+    Ok(Verdict {
+        samples,
+        mbps,
+        download_throttled,
+        shape,
+        congested,
+        upload,
+        probe,
+        throttled,
+    })
+}
+
+/// 对一个方向测速，低于阈值时按配置复测：(所有采样, 中位数, 是否限速)
+async fn sample_confirmed(
+    config: &Config,
+    direction: Direction,
+    threshold: f64,
+) -> Result<(Vec<SpeedSample>, f64, bool)> {
+    let label = direction.label();
+    let first = direction.measure(config).await?;
+    let first_mbps = first.mbps;
+    let mut samples = vec![first];
+
+    if first_mbps < threshold && config.confirm_samples > 0 {
+        info!(
+            "首次{}测速 {:.2} Mbps 低于阈值，{} 秒后开始复测（共 {} 次）",
+            label, first_mbps, config.confirm_interval_secs, config.confirm_samples
+        );
+        for i in 0..config.confirm_samples {
+            tokio::time::sleep(Duration::from_secs(config.confirm_interval_secs)).await;
+            match direction.measure(config).await {
+                Ok(sample) => {
+                    info!("第 {} 次{}复测: {:.2} Mbps", i + 1, label, sample.mbps);
+                    samples.push(sample);
+                }
+                Err(e) => warn!("第 {} 次{}复测失败: {:#}", i + 1, label, e),
+            }
+        }
+    }
+
+    let speeds: Vec<f64> = samples.iter().map(|s| s.mbps).collect();
+    let mbps = median(&speeds);
+    direction.record(&config.data_dir, &samples, mbps);
+    let throttled = match config.confirm_quorum {
+        Some(quorum) => speeds.iter().filter(|s| **s < threshold).count() as u32 >= quorum,
+        None => mbps < threshold,
+    };
+
+    if samples.len() > 1 && !throttled {
+        info!("{}复测未确认限速，视为临时波动（中位数 {:.2} Mbps）", label, mbps);
+    }
+
+    Ok((samples, mbps, throttled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SpeedtestBackend;
+
+    /// 在随机端口上启动对端测速服务，返回其地址
+    async fn start_peer(secret: &str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::peer::router(secret.to_string());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    fn peer_settings(base: &str, secret: &str) -> SpeedtestConfig {
+        SpeedtestConfig {
+            backend: SpeedtestBackend::Http,
+            iperf3_server: None,
+            download_urls: vec![format!("{}/__down?bytes=1048576&secret={}", base, secret)],
+            upload_url: format!("{}/__up?secret={}", base, secret),
+            duration_secs: 1,
+            timeout_secs: 5,
+            user_agent: "test".to_string(),
+            streams: 1,
+            bind_interface: None,
+            source_ip: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn peer_download_and_upload() {
+        let base = start_peer("s3cret").await;
+        let settings = peer_settings(&base, "s3cret");
+        assert!(measure_download_speed(&settings).await.unwrap().bytes > 0);
+        assert!(measure_upload_speed(&settings).await.unwrap().bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_fail_the_test() {
+        let base = start_peer("s3cret").await;
+        let settings = peer_settings(&base, "wrong");
+
+        let err = measure_download_speed(&settings).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("403"), "{:#}", err);
+        let err = measure_upload_speed(&settings).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("403"), "{:#}", err);
+    }
 }
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::rpc::AliyunRpcClient;
+
+/// 轻量应用服务器 API 版本
+const API_VERSION: &str = "2020-06-01";
+/// 未指定地域时用于查询地域列表的地域
+const DEFAULT_REGION: &str = "cn-hangzhou";
+
+/// 轻量应用服务器（SWAS）API 客户端
+pub struct SwasClient {
+    rpc: AliyunRpcClient,
+    /// 配置的实例所在地域，不设置时遍历所有地域查找
+    region_id: Option<String>,
+}
+
+/// 从 SWAS 查到的实例信息
+#[derive(Debug, Clone)]
+pub struct InstanceInfo {
+    pub instance_id: String,
+    pub instance_name: Option<String>,
+    pub region_id: String,
+    /// 地域名称，如 "香港"、"杭州"
+    pub region_name: String,
+    /// 套餐带宽（Mbps）
+    pub bandwidth_mbps: u32,
+    pub public_ip: Option<String>,
+}
+
+impl InstanceInfo {
+    /// 单行说明，如 "sw-xxx（香港 cn-hongkong，30 Mbps，1.2.3.4）"
+    pub fn line(&self) -> String {
+        let name = match &self.instance_name {
+            Some(n) if !n.is_empty() => format!("{} ", n),
+            _ => String::new(),
+        };
+        format!(
+            "{}{}（{} {}，{} Mbps，{}）",
+            name,
+            self.instance_id,
+            self.region_name,
+            self.region_id,
+            self.bandwidth_mbps,
+            self.public_ip.as_deref().unwrap_or("无公网 IP")
+        )
+    }
+}
+
+/// 实例当月流量包的使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct TrafficUsage {
+    /// 流量包总量（字节）
+    pub total_bytes: u64,
+    /// 已用流量（字节）
+    pub used_bytes: u64,
+    /// 超出流量包的部分（字节）
+    pub overflow_bytes: u64,
+}
+
+impl TrafficUsage {
+    /// 流量包是否已用完
+    pub fn exhausted(&self) -> bool {
+        self.total_bytes > 0 && (self.overflow_bytes > 0 || self.used_bytes >= self.total_bytes)
+    }
+
+    /// 流量包重置日期（下个月 1 日）
+    pub fn resets_on(&self) -> chrono::NaiveDate {
+        use chrono::Datelike;
+        let today = chrono::Local::now().date_naive();
+        let (year, month) = if today.month() == 12 {
+            (today.year() + 1, 1)
+        } else {
+            (today.year(), today.month() + 1)
+        };
+        chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap_or(today)
+    }
+
+    /// 单行说明，如 "已用 812.3 / 1024.0 GB（79%）"
+    pub fn line(&self) -> String {
+        let gb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+        let percent = if self.total_bytes > 0 {
+            self.used_bytes as f64 / self.total_bytes as f64 * 100.0
+        } else {
+            0.0
+        };
+        let mut line = format!(
+            "已用 {:.1} / {:.1} GB（{:.0}%）",
+            gb(self.used_bytes),
+            gb(self.total_bytes),
+            percent
+        );
+        if self.overflow_bytes > 0 {
+            line.push_str(&format!("，超出 {:.1} GB", gb(self.overflow_bytes)));
+        }
+        line
+    }
+}
+
+// ---- API 响应结构 ----
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RegionsResponse {
+    regions: Option<Vec<Region>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Region {
+    region_id: Option<String>,
+    local_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InstancesResponse {
+    instances: Option<Vec<Instance>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Instance {
+    instance_id: Option<String>,
+    instance_name: Option<String>,
+    plan_id: Option<String>,
+    public_ip_address: Option<String>,
+    resource_spec: Option<ResourceSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceSpec {
+    bandwidth: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrafficPackagesResponse {
+    instance_traffic_package_usages: Option<Vec<TrafficPackageUsage>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TrafficPackageUsage {
+    instance_id: Option<String>,
+    traffic_package_total: Option<u64>,
+    traffic_used: Option<u64>,
+    traffic_overflow: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PlansResponse {
+    plans: Option<Vec<Plan>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Plan {
+    plan_id: Option<String>,
+    bandwidth: Option<u32>,
+}
+
+/// 地域名称去掉 "中国" 前缀和括号外的编号，如 "中国香港" → "香港"、"华东1（杭州）" → "杭州"
+fn short_region_name(local_name: &str) -> String {
+    if let Some((_, rest)) = local_name.split_once('（') {
+        if let Some((name, _)) = rest.split_once('）') {
+            return name.to_string();
+        }
+    }
+    local_name
+        .strip_prefix("中国")
+        .unwrap_or(local_name)
+        .to_string()
+}
+
+impl SwasClient {
+    pub fn new(config: &Config) -> Self {
+        let region = config.region_id.as_deref().unwrap_or(DEFAULT_REGION);
+        Self {
+            rpc: AliyunRpcClient::new(
+                config,
+                format!("swas.{}.aliyuncs.com", region),
+                API_VERSION,
+            ),
+            region_id: config.region_id.clone(),
+        }
+    }
+
+    /// 指定地域的 API 客户端
+    fn regional(&self, region_id: &str) -> AliyunRpcClient {
+        self.rpc.with_endpoint(format!("swas.{}.aliyuncs.com", region_id))
+    }
+
+    /// 查询可用地域：(RegionId, 地域名称)
+    async fn list_regions(&self) -> Result<Vec<(String, String)>> {
+        let region = self.region_id.as_deref().unwrap_or(DEFAULT_REGION);
+        let resp: RegionsResponse = self
+            .regional(region)
+            .get("ListRegions", &BTreeMap::new())
+            .await?;
+        Ok(resp
+            .regions
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|r| {
+                let id = r.region_id?;
+                let name = r
+                    .local_name
+                    .as_deref()
+                    .map(short_region_name)
+                    .unwrap_or_else(|| id.clone());
+                Some((id, name))
+            })
+            .collect())
+    }
+
+    /// 在指定地域查找实例
+    async fn find_instance(&self, region_id: &str, instance_id: &str) -> Result<Option<Instance>> {
+        let mut params = BTreeMap::new();
+        params.insert("RegionId".to_string(), region_id.to_string());
+        params.insert(
+            "InstanceIds".to_string(),
+            serde_json::to_string(&[instance_id])?,
+        );
+        let resp: InstancesResponse =
+            self.regional(region_id).get("ListInstances", &params).await?;
+        Ok(resp
+            .instances
+            .unwrap_or_default()
+            .into_iter()
+            .find(|i| i.instance_id.as_deref() == Some(instance_id)))
+    }
+
+    /// 通过 ListPlans 查询套餐带宽（实例信息里没有带宽时使用）
+    async fn plan_bandwidth(&self, region_id: &str, plan_id: &str) -> Result<u32> {
+        let mut params = BTreeMap::new();
+        params.insert("RegionId".to_string(), region_id.to_string());
+        let resp: PlansResponse = self.regional(region_id).get("ListPlans", &params).await?;
+        resp.plans
+            .unwrap_or_default()
+            .into_iter()
+            .find(|p| p.plan_id.as_deref() == Some(plan_id))
+            .and_then(|p| p.bandwidth)
+            .with_context(|| format!("未找到套餐 {} 的带宽", plan_id))
+    }
+
+    /// 查询实例的地域、套餐带宽和公网 IP
+    ///
+    /// 配置了 region_id 时只查这个地域，否则依次查询所有地域。
+    pub async fn describe_instance(&self, instance_id: &str) -> Result<InstanceInfo> {
+        info!("正在查询轻量应用服务器实例 {}...", instance_id);
+        let regions = self.list_regions().await?;
+        let candidates: Vec<(String, String)> = match &self.region_id {
+            Some(region) => {
+                let name = regions
+                    .iter()
+                    .find(|(id, _)| id == region)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| region.clone());
+                vec![(region.clone(), name)]
+            }
+            None => regions,
+        };
+
+        for (region_id, region_name) in candidates {
+            let instance = match self.find_instance(&region_id, instance_id).await {
+                Ok(Some(i)) => i,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("查询地域 {} 的实例失败: {:#}", region_id, e);
+                    continue;
+                }
+            };
+
+            let bandwidth_mbps = match instance.resource_spec.as_ref().and_then(|s| s.bandwidth) {
+                Some(b) => b,
+                None => {
+                    let plan_id = instance.plan_id.as_deref().context("实例信息中没有套餐 ID")?;
+                    self.plan_bandwidth(&region_id, plan_id).await?
+                }
+            };
+
+            let info = InstanceInfo {
+                instance_id: instance_id.to_string(),
+                instance_name: instance.instance_name,
+                region_id,
+                region_name,
+                bandwidth_mbps,
+                public_ip: instance.public_ip_address.filter(|ip| !ip.is_empty()),
+            };
+            info!("找到实例: {}", info.line());
+            return Ok(info);
+        }
+
+        anyhow::bail!(
+            "未找到实例 {}，请检查 instance_id 和 region_id 是否正确",
+            instance_id
+        )
+    }
+
+    /// 查询实例当月流量包的使用情况
+    pub async fn traffic_usage(&self, instance: &InstanceInfo) -> Result<TrafficUsage> {
+        let mut params = BTreeMap::new();
+        params.insert("RegionId".to_string(), instance.region_id.clone());
+        params.insert(
+            "InstanceIds".to_string(),
+            serde_json::to_string(&[&instance.instance_id])?,
+        );
+        let resp: TrafficPackagesResponse = self
+            .regional(&instance.region_id)
+            .get("ListInstancesTrafficPackages", &params)
+            .await?;
+        let usage = resp
+            .instance_traffic_package_usages
+            .unwrap_or_default()
+            .into_iter()
+            .find(|u| u.instance_id.as_deref() == Some(instance.instance_id.as_str()))
+            .with_context(|| format!("未查到实例 {} 的流量包", instance.instance_id))?;
+        Ok(TrafficUsage {
+            total_bytes: usage.traffic_package_total.unwrap_or(0),
+            used_bytes: usage.traffic_used.unwrap_or(0),
+            overflow_bytes: usage.traffic_overflow.unwrap_or(0),
+        })
+    }
+}
+
+/// 提交工单前检查流量包
+///
+/// 流量包用完后被限速属于正常现象，此时返回的错误中包含用量和重置日期。
+/// 未查询到实例或查询失败时不拦截。
+pub async fn check_traffic(config: &Config) -> Result<()> {
+    let Some(instance) = &config.instance else {
+        return Ok(());
+    };
+    match SwasClient::new(config).traffic_usage(instance).await {
+        Ok(usage) if usage.exhausted() => anyhow::bail!(
+            "流量包已用完（{}），将于 {} 重置，此时限速属于正常现象，不提交工单",
+            usage.line(),
+            usage.resets_on().format("%Y-%m-%d")
+        ),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("查询流量包失败，继续提交流程: {:#}", e);
+            Ok(())
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 use teloxide::prelude::*;
@@ -8,7 +9,12 @@ use tracing::{error, info, warn};
 
 use crate::client::WorkorderClient;
 use crate::config::Config;
-use crate::{speedtest, templates};
+use crate::passive::PassiveMonitor;
+use crate::tracker::TicketTracker;
+use crate::{followup, governor, history, probe, shape, speedtest, swas, templates};
+
+/// 最多保留多少个未处理的审批（更早的按钮点击后提示已失效）
+const MAX_PENDING_APPROVALS: usize = 20;
 
 /// Bot 共享状态
 struct BotState {
@@ -16,6 +22,28 @@ struct BotState {
     last_speed: Option<f64>,
     last_check_time: Option<chrono::DateTime<chrono::Local>>,
     start_time: chrono::DateTime<chrono::Local>,
+    tracker: Arc<TicketTracker>,
+    monitor: Option<Arc<PassiveMonitor>>,
+    /// 等待审批的判定结果：(短 ID, 判定)，按钮只携带 ID，避免超过 callback_data 的 64 字节限制
+    pending_approvals: VecDeque<(String, speedtest::Verdict)>,
+}
+
+impl BotState {
+    /// 保存一次判定结果，返回审批按钮使用的短 ID
+    fn add_approval(&mut self, verdict: speedtest::Verdict) -> String {
+        let id = format!("{:08x}", rand::random::<u32>());
+        if self.pending_approvals.len() >= MAX_PENDING_APPROVALS {
+            self.pending_approvals.pop_front();
+        }
+        self.pending_approvals.push_back((id.clone(), verdict));
+        id
+    }
+
+    /// 取出（并移除）审批对应的判定结果，重复点击或已失效时返回 None
+    fn take_approval(&mut self, id: &str) -> Option<speedtest::Verdict> {
+        let idx = self.pending_approvals.iter().position(|(i, _)| i == id)?;
+        self.pending_approvals.remove(idx).map(|(_, verdict)| verdict)
+    }
 }
 
 type SharedState = Arc<Mutex<BotState>>;
@@ -34,6 +62,8 @@ enum Command {
     Submit,
     #[command(description = "查看当前状态")]
     Status,
+    #[command(description = "查看最近的测速和工单记录")]
+    History,
 }
 
 /// 检查是否为授权用户
@@ -69,6 +99,7 @@ async fn handle_command(
                         /speed \\- 仅测速\n\
                         /submit \\- 直接提交工单\n\
                         /status \\- 查看状态\n\
+                        /history \\- 历史记录\n\
                         /help \\- 显示帮助";
             bot.send_message(chat_id, text)
                 .parse_mode(ParseMode::MarkdownV2)
@@ -77,15 +108,17 @@ async fn handle_command(
 
         Command::Check => {
             let s = state.lock().await;
-            let threshold = s.config.speed_threshold;
-            let auto_submit = s.config.auto_submit;
+            let base_cfg = s.config.clone();
             drop(s);
+            let threshold = base_cfg.speed_threshold;
+            let auto_submit = base_cfg.auto_submit;
 
             bot.send_message(chat_id, format!("⏳ 正在测速，阈值: {} Mbps ...", threshold))
                 .await?;
 
-            match speedtest::measure_download_speed().await {
-                Ok(speed) => {
+            match speedtest::check_throttle(&base_cfg).await {
+                Ok(verdict) => {
+                    let speed = verdict.mbps;
                     // 更新状态
                     {
                         let mut s = state.lock().await;
@@ -93,30 +126,75 @@ async fn handle_command(
                         s.last_check_time = Some(chrono::Local::now());
                     }
 
-                    if speed < threshold {
+                    if verdict.throttled {
+                        // 已有工单时不再重复提交（处理中则跳过，已处理仍限速则追加回复）
+                        let s = state.lock().await;
+                        let cfg = s.config.clone();
+                        let tracker = s.tracker.clone();
+                        drop(s);
+                        // 流量包用完导致的限速不提交也不追加回复
+                        if let Err(e) = swas::check_traffic(&cfg).await {
+                            bot.send_message(
+                                chat_id,
+                                format!(
+                                    "⚠️ {}\n下载速度: {:.2} Mbps（阈值: {} Mbps）{}\n📦 {:#}",
+                                    verdict.alert_reasons(),
+                                    speed,
+                                    threshold,
+                                    verdict.detail(),
+                                    e
+                                ),
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+                        if let Some(note) =
+                            followup::handle_existing_ticket(&cfg, &tracker, speed).await
+                        {
+                            bot.send_message(
+                                chat_id,
+                                format!(
+                                    "⚠️ {}\n下载速度: {:.2} Mbps（阈值: {} Mbps）{}\n{}",
+                                    verdict.alert_reasons(),
+                                    speed,
+                                    threshold,
+                                    verdict.detail(),
+                                    note
+                                ),
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+
                         if auto_submit {
                             // 自动提交模式
                             bot.send_message(
                                 chat_id,
                                 format!(
-                                    "⚠️ 下载速度: {:.2} Mbps（低于阈值 {} Mbps）\n正在自动提交工单...",
-                                    speed, threshold
+                                    "⚠️ {}\n下载速度: {:.2} Mbps（阈值: {} Mbps）{}\n正在自动提交工单...",
+                                    verdict.alert_reasons(),
+                                    speed,
+                                    threshold,
+                                    verdict.detail()
                                 ),
                             )
                             .await?;
 
                             let s = state.lock().await;
                             let mut cfg = s.config.clone();
+                            let tracker = s.tracker.clone();
                             drop(s);
-                            cfg.ticket_title = templates::random_title();
-                            cfg.ticket_description = templates::random_description(speed);
+                            let ctx = cfg.ticket_context();
+                            cfg.ticket_title = templates::random_title(&ctx);
+                            cfg.ticket_description = verdict.ticket_description(&ctx);
 
                             let client = WorkorderClient::new(cfg);
-                            match client.submit_ticket().await {
-                                Ok(ticket_id) => {
+                            match client.submit_ticket("telegram").await {
+                                Ok(ticket) => {
+                                    tracker.track(&ticket.ticket_id).await;
                                     bot.send_message(
                                         chat_id,
-                                        format!("✅ 工单提交成功，工单号: {}", ticket_id),
+                                        format!("✅ 工单提交成功，工单号: {}", ticket.ticket_id),
                                     )
                                     .await?;
                                 }
@@ -128,28 +206,72 @@ async fn handle_command(
                                     .await?;
                                 }
                             }
+                        } else if let Err(e) = governor::check(&cfg) {
+                            // 已达提交上限，不再询问是否提交
+                            bot.send_message(
+                                chat_id,
+                                format!(
+                                    "⚠️ {}\n下载速度: {:.2} Mbps（阈值: {} Mbps）{}\n⏸ {:#}",
+                                    verdict.alert_reasons(),
+                                    speed,
+                                    threshold,
+                                    verdict.detail(),
+                                    e
+                                ),
+                            )
+                            .await?;
                         } else {
                             // 审批模式：发送带按钮的消息
+                            // 判定结果保存在 Bot 状态中，按钮只带短 ID，提交时按它生成工单描述
+                            let approval_id = state.lock().await.add_approval(verdict.clone());
                             let buttons = vec![vec![
-                                InlineKeyboardButton::callback("✅ 提交工单", format!("submit:{:.2}", speed)),
+                                InlineKeyboardButton::callback(
+                                    "✅ 提交工单",
+                                    format!("submit:{}", approval_id),
+                                ),
                                 InlineKeyboardButton::callback("❌ 取消", "cancel"),
                             ]];
                             bot.send_message(
                                 chat_id,
                                 format!(
-                                    "⚠️ 带宽限速告警\n\n下载速度: {:.2} Mbps\n阈值: {} Mbps\n\n是否提交工单？",
-                                    speed, threshold
+                                    "⚠️ 带宽限速告警（{}）\n\n下载速度: {:.2} Mbps\n阈值: {} Mbps{}\n\n是否提交工单？",
+                                    verdict.alert_reasons(),
+                                    speed,
+                                    threshold,
+                                    verdict.detail()
                                 ),
                             )
                             .reply_markup(InlineKeyboardMarkup::new(buttons))
                             .await?;
                         }
+                    } else if verdict.congested {
+                        bot.send_message(
+                            chat_id,
+                            format!(
+                                "🌫 下载速度 {:.2} Mbps 低于阈值 {} Mbps，但吞吐波动明显，判定为网络拥塞，暂不提交工单{}",
+                                speed,
+                                threshold,
+                                verdict.detail()
+                            ),
+                        )
+                        .await?;
                     } else {
                         bot.send_message(
                             chat_id,
-                            format!("✅ 速度正常: {:.2} Mbps（阈值: {} Mbps）", speed, threshold),
+                            format!(
+                                "✅ 速度正常: {:.2} Mbps（阈值: {} Mbps）{}",
+                                speed,
+                                threshold,
+                                verdict.detail()
+                            ),
                         )
                         .await?;
+
+                        let s = state.lock().await;
+                        let cfg = s.config.clone();
+                        let tracker = s.tracker.clone();
+                        drop(s);
+                        followup::close_recovered_tickets(&cfg, &tracker, speed).await;
                     }
                 }
                 Err(e) => {
@@ -162,21 +284,53 @@ async fn handle_command(
         Command::Speed => {
             bot.send_message(chat_id, "⏳ 正在测速...").await?;
 
-            match speedtest::measure_download_speed().await {
-                Ok(speed) => {
+            let cfg = state.lock().await.config.clone();
+            if cfg.probe.enabled() {
+                let report = probe::run(&cfg.probe).await;
+                history::record_probe(&cfg.data_dir, &report);
+                bot.send_message(chat_id, format!("📶 延迟探测{}", report.detail()))
+                    .await?;
+            }
+            match speedtest::measure_download_speed(&cfg.speedtest).await {
+                Ok(sample) => {
+                    let speed = sample.mbps;
                     {
                         let mut s = state.lock().await;
+                        history::record_speed(&s.config.data_dir, &sample);
                         s.last_speed = Some(speed);
                         s.last_check_time = Some(chrono::Local::now());
                     }
-                    bot.send_message(chat_id, format!("📊 下载速度: {:.2} Mbps", speed))
-                        .await?;
+                    let shape = shape::classify(&[&sample.series], cfg.speed_threshold)
+                        .map(|s| s.line())
+                        .unwrap_or_default();
+                    bot.send_message(
+                        chat_id,
+                        format!("📊 下载速度: {:.2} Mbps{}{}", speed, sample.streams_line(), shape),
+                    )
+                    .await?;
                 }
                 Err(e) => {
                     bot.send_message(chat_id, format!("❌ 测速失败: {:#}", e))
                         .await?;
                 }
             }
+
+            if cfg.upload_threshold.is_some() {
+                match speedtest::measure_upload_speed(&cfg.speedtest).await {
+                    Ok(sample) => {
+                        history::record_upload(&cfg.data_dir, &sample);
+                        bot.send_message(
+                            chat_id,
+                            format!("📤 上传速度: {:.2} Mbps{}", sample.mbps, sample.streams_line()),
+                        )
+                        .await?;
+                    }
+                    Err(e) => {
+                        bot.send_message(chat_id, format!("❌ 上传测速失败: {:#}", e))
+                            .await?;
+                    }
+                }
+            }
         }
 
         Command::Submit => {
@@ -190,6 +344,20 @@ async fn handle_command(
         }
 
         Command::Status => {
+            // 流量包要现查，先查完再加锁
+            let cfg = state.lock().await.config.clone();
+            let instance_str = match &cfg.instance {
+                Some(i) => {
+                    let usage = match swas::SwasClient::new(&cfg).traffic_usage(i).await {
+                        Ok(u) if u.exhausted() => format!("{} ⚠️ 已用完", u.line()),
+                        Ok(u) => u.line(),
+                        Err(e) => format!("查询失败: {:#}", e),
+                    };
+                    format!("\n实例: {}\n流量包: {}", i.line(), usage)
+                }
+                None => String::new(),
+            };
+
             let s = state.lock().await;
             let uptime = chrono::Local::now() - s.start_time;
             let hours = uptime.num_hours();
@@ -204,18 +372,30 @@ async fn handle_command(
                 None => "无".to_string(),
             };
 
+            let probe_str = match history::last_probe(&s.config.data_dir) {
+                Some(r) => format!("\n上次延迟: {}", r.summary()),
+                None => String::new(),
+            };
+            let traffic_str = match s.monitor.as_ref().and_then(|m| m.summary()) {
+                Some(w) => format!("\n实时流量: {}", w.line()),
+                None => String::new(),
+            };
+
             let text = format!(
                 "📊 *状态信息*\n\n\
-                 运行时长: {}h {}m\n\
+                 运行时长: {}h {}m{}\n\
                  上次测速: {}\n\
-                 上次结果: {}\n\
+                 上次结果: {}{}{}\n\
                  速度阈值: {} Mbps\n\
                  自动提交: {}\n\
                  定时任务: {}",
                 hours,
                 minutes,
+                instance_str,
                 last_time_str,
                 last_speed_str,
+                probe_str,
+                traffic_str,
                 s.config.speed_threshold,
                 if s.config.auto_submit { "开启" } else { "关闭" },
                 s.config.cron_expression
@@ -224,6 +404,20 @@ async fn handle_command(
 
             bot.send_message(chat_id, text).await?;
         }
+
+        Command::History => {
+            let s = state.lock().await;
+            let records = history::recent(&s.config.data_dir, 15);
+            drop(s);
+
+            let text = if records.is_empty() {
+                "📜 暂无历史记录".to_string()
+            } else {
+                let lines: Vec<String> = records.iter().map(|r| r.summary()).collect();
+                format!("📜 最近记录\n\n{}", lines.join("\n"))
+            };
+            bot.send_message(chat_id, text).await?;
+        }
     }
 
     Ok(())
@@ -255,36 +449,52 @@ async fn handle_callback(
     }
 
     if data == "cancel" {
+        {
+            let s = state.lock().await;
+            history::record_decision(&s.config.data_dir, "telegram", false);
+        }
         bot.answer_callback_query(&q.id).text("已取消").await?;
         bot.send_message(chat_id, "❌ 已取消").await?;
         return Ok(());
     }
 
     if data.starts_with("submit:") || data == "force_submit" {
+        let payload = data.strip_prefix("submit:").unwrap_or("");
+        let verdict = if payload.is_empty() {
+            None
+        } else {
+            let verdict = state.lock().await.take_approval(payload);
+            if verdict.is_none() {
+                bot.answer_callback_query(&q.id).text("审批已失效").await?;
+                bot.send_message(chat_id, "⌛ 这条审批已处理或已失效（程序重启过），请重新 /check")
+                    .await?;
+                return Ok(());
+            }
+            verdict
+        };
+
         bot.answer_callback_query(&q.id).text("正在提交...").await?;
         bot.send_message(chat_id, "⏳ 正在提交工单...").await?;
 
         let s = state.lock().await;
         let mut cfg = s.config.clone();
+        let tracker = s.tracker.clone();
         drop(s);
-
-        // 如果是从 check 流程来的，speed 信息在 data 里
-        let speed = if data.starts_with("submit:") {
-            data.trim_start_matches("submit:").parse::<f64>().ok()
-        } else {
-            None
-        };
-
-        cfg.ticket_title = templates::random_title();
-        cfg.ticket_description = match speed {
-            Some(s) => templates::random_description(s),
-            None => templates::random_description(0.0),
+        history::record_decision(&cfg.data_dir, "telegram", true);
+
+        // 从 check 流程来的审批与定时任务、飞书审批使用同一份工单描述
+        let ctx = cfg.ticket_context();
+        cfg.ticket_title = templates::random_title(&ctx);
+        cfg.ticket_description = match &verdict {
+            Some(v) => v.ticket_description(&ctx),
+            None => templates::random_description(&ctx, 0.0, &[]),
         };
 
         let client = WorkorderClient::new(cfg);
-        match client.submit_ticket().await {
-            Ok(ticket_id) => {
-                bot.send_message(chat_id, format!("✅ 工单提交成功，工单号: {}", ticket_id))
+        match client.submit_ticket("telegram").await {
+            Ok(ticket) => {
+                tracker.track(&ticket.ticket_id).await;
+                bot.send_message(chat_id, format!("✅ 工单提交成功，工单号: {}", ticket.ticket_id))
                     .await?;
             }
             Err(e) => {
@@ -307,7 +517,11 @@ pub async fn send_message(token: &str, chat_id: i64, text: &str) -> anyhow::Resu
 }
 
 /// 启动 Telegram Bot（long polling 模式）
-pub async fn start_bot(config: Config) {
+pub async fn start_bot(
+    config: Config,
+    tracker: Arc<TicketTracker>,
+    monitor: Option<Arc<PassiveMonitor>>,
+) {
     let token = match &config.telegram_bot_token {
         Some(t) => t.clone(),
         None => return,
@@ -330,11 +544,17 @@ pub async fn start_bot(config: Config) {
         warn!("设置 Bot 命令菜单失败: {}", e);
     }
 
+    // 从历史记录恢复上次测速结果
+    let last = history::last_speed(&config.data_dir);
+
     let state: SharedState = Arc::new(Mutex::new(BotState {
         config,
-        last_speed: None,
-        last_check_time: None,
+        last_speed: last.map(|(_, speed)| speed),
+        last_check_time: last.map(|(time, _)| time),
         start_time: chrono::Local::now(),
+        tracker,
+        monitor,
+        pending_approvals: VecDeque::new(),
     }));
 
     let handler = dptree::entry()
@@ -1,29 +1,86 @@
 use rand::seq::SliceRandom;
 use rand::Rng;
 
+/// 工单文案中引用的实例信息
+#[derive(Debug, Clone)]
+pub struct TicketContext {
+    /// 地域名称，如 "香港"
+    pub region: String,
+    /// 套餐带宽（Mbps）
+    pub bandwidth_mbps: u32,
+}
+
+impl Default for TicketContext {
+    /// 未配置 instance_id 或查询失败时沿用的文案（香港 30Mbps 套餐）
+    fn default() -> Self {
+        Self {
+            region: "香港".to_string(),
+            bandwidth_mbps: 30,
+        }
+    }
+}
+
+/// 内置工单标题模板，`{region}` 和 `{bw}` 替换为地域和套餐带宽
+const TITLES: &[&str] = &[
+    "{region}轻量应用服务器带宽被限速，请帮忙检查解除",
+    "我的{region}轻量服务器网速异常，请协助处理下",
+    "轻量应用服务器实际带宽远低于购买规格，请核实",
+    "{region}服务器带宽好像被限制了，麻烦帮看下",
+    "轻量服务器下载速度变得很慢，请帮忙排查",
+    "{region}轻量服务器网络受限，请帮忙解除带宽限速",
+    "服务器带宽不达标，下载速度远低于{bw}Mbps",
+    "我的轻量应用服务器带宽好像被限速了，请检查",
+    "{region}轻量服务器带宽问题咨询",
+    "轻量服务器带宽异常，下载很慢请帮忙看看",
+    "{region}轻量应用服务器带宽严重缩水",
+    "轻量服务器实际网速跟购买时差距很大",
+];
+
+fn fill(template: &str, ctx: &TicketContext) -> String {
+    template
+        .replace("{region}", &ctx.region)
+        .replace("{bw}", &ctx.bandwidth_mbps.to_string())
+}
+
 /// 生成随机工单标题
-pub fn random_title() -> String {
-    let titles: &[&str] = &[
-        "香港轻量应用服务器带宽被限速，请帮忙检查解除",
-        "我的香港轻量服务器网速异常，请协助处理下",
-        "轻量应用服务器实际带宽远低于购买规格，请核实",
-        "香港服务器带宽好像被限制了，麻烦帮看下",
-        "轻量服务器下载速度变得很慢，请帮忙排查",
-        "香港轻量服务器网络受限，请帮忙解除带宽限速",
-        "服务器带宽不达标，下载速度远低于30Mbps",
-        "我的轻量应用服务器带宽好像被限速了，请检查",
-        "香港轻量服务器带宽问题咨询",
-        "轻量服务器带宽异常，下载很慢请帮忙看看",
-        "香港轻量应用服务器带宽严重缩水",
-        "轻量服务器实际网速跟购买时差距很大",
-    ];
+pub fn random_title(ctx: &TicketContext) -> String {
     let mut rng = rand::thread_rng();
-    titles.choose(&mut rng).unwrap().to_string()
+    fill(TITLES.choose(&mut rng).unwrap(), ctx)
+}
+
+/// 判断标题是否由本工具的模板生成（也认得按默认文案生成的旧标题）
+pub fn is_generated_title(title: &str, ctx: &TicketContext) -> bool {
+    let default_ctx = TicketContext::default();
+    TITLES
+        .iter()
+        .any(|t| fill(t, ctx) == title || fill(t, &default_ctx) == title)
+}
+
+/// 配置文件未设置 ticket_title 时的默认标题
+pub fn default_title(ctx: &TicketContext) -> String {
+    fill(TITLES[0], ctx)
+}
+
+/// 配置文件未设置 ticket_description 时的默认描述（直接提交工单时使用）
+pub fn default_description(ctx: &TicketContext) -> String {
+    let region = &ctx.region;
+    let bw = ctx.bandwidth_mbps;
+    format!(
+        "您好，我购买的{region}轻量应用服务器带宽为{bw}Mbps，\
+        但目前实际带宽明显低于这个数值。\
+        请帮忙检查服务器是否存在带宽限速情况，\
+        如果存在限速请帮忙解除，恢复到购买时承诺的{bw}Mbps带宽。\
+        谢谢！"
+    )
 }
 
 /// 生成随机工单描述（包含实测速度数据，使每次内容自然不同）
-pub fn random_description(speed_mbps: f64) -> String {
+///
+/// `samples` 为多次复测的结果，超过一次时会附上每次的速度。
+pub fn random_description(ctx: &TicketContext, speed_mbps: f64, samples: &[f64]) -> String {
     let mut rng = rand::thread_rng();
+    let region = &ctx.region;
+    let bw = ctx.bandwidth_mbps;
 
     // 速度显示格式随机化
     let speed_str = if rng.gen_bool(0.5) {
@@ -32,58 +89,57 @@ pub fn random_description(speed_mbps: f64) -> String {
         format!("{:.1}", speed_mbps)
     };
 
-    let greetings: &[&str] = &["您好，", "你好，", "您好！\n", ""];
-    let greeting = *greetings.choose(&mut rng).unwrap();
+    let greeting = random_greeting();
 
     let bodies: Vec<String> = vec![
         format!(
-            "我购买的香港轻量应用服务器带宽为30Mbps，\
+            "我购买的{region}轻量应用服务器带宽为{bw}Mbps，\
             但目前实际带宽只有约{}Mbps左右。\
             请帮忙检查服务器是否存在带宽限速情况，\
-            如果存在限速请帮忙解除，恢复到购买时承诺的30Mbps带宽。",
+            如果存在限速请帮忙解除，恢复到购买时承诺的{bw}Mbps带宽。",
             speed_str
         ),
         format!(
-            "我的香港轻量应用服务器最近网速很慢，\
+            "我的{region}轻量应用服务器最近网速很慢，\
             刚测了一下下载速度只有{}Mbps，\
-            我买的是30Mbps的套餐。\
+            我买的是{bw}Mbps的套餐。\
             能帮我看看是不是被限速了吗？如果是的话麻烦帮忙解除一下。",
             speed_str
         ),
         format!(
-            "我有一台香港的轻量应用服务器，配置的带宽是30Mbps，\
+            "我有一台{region}的轻量应用服务器，配置的带宽是{bw}Mbps，\
             但我刚测试了下载速度只有大概{}Mbps，感觉被限速了。\
             请帮忙检查一下，如果确实有限速的话帮忙解除。",
             speed_str
         ),
         format!(
-            "我在用香港轻量应用服务器，带宽套餐是30Mbps的，\
+            "我在用{region}轻量应用服务器，带宽套餐是{bw}Mbps的，\
             但是实测下载只有{}Mbps，速度明显不对。\
             麻烦帮忙查一下是不是有限速，帮忙处理一下。",
             speed_str
         ),
         format!(
-            "我的香港轻量服务器30Mbps带宽，\
+            "我的{region}轻量服务器{bw}Mbps带宽，\
             现在实际下载速度只有{}Mbps，\
             跟购买时承诺的差太多了。\
             请帮忙看看是怎么回事，是否可以恢复正常带宽。",
             speed_str
         ),
         format!(
-            "我发现我的香港轻量应用服务器带宽有问题。\
-            购买的是30Mbps，但测速只有{}Mbps。\
+            "我发现我的{region}轻量应用服务器带宽有问题。\
+            购买的是{bw}Mbps，但测速只有{}Mbps。\
             请问是被限速了吗？能否帮忙检查处理一下？",
             speed_str
         ),
         format!(
-            "我购买了香港区域的轻量应用服务器，标注带宽30Mbps。\
+            "我购买了{region}区域的轻量应用服务器，标注带宽{bw}Mbps。\
             但是今天测试发现下载速度只有{}Mbps，\
             严重低于标称值。请帮我检查一下是否存在限速，\
             如有限速请帮忙恢复。",
             speed_str
         ),
         format!(
-            "香港轻量应用服务器的带宽应该是30Mbps，\
+            "{region}轻量应用服务器的带宽应该是{bw}Mbps，\
             但我实际测试下来只有{}Mbps。\
             请问这个是什么情况？能帮忙看看吗？",
             speed_str
@@ -91,6 +147,119 @@ pub fn random_description(speed_mbps: f64) -> String {
     ];
     let body = bodies.choose(&mut rng).unwrap();
 
+    format!("{}{}{}{}", greeting, body, samples_detail(samples), random_ending())
+}
+
+/// 生成只有上传限速时的工单描述
+pub fn random_upload_description(ctx: &TicketContext, upload_mbps: f64, samples: &[f64]) -> String {
+    let mut rng = rand::thread_rng();
+    let region = &ctx.region;
+    let bw = ctx.bandwidth_mbps;
+    let speed_str = format!("{:.1}", upload_mbps);
+
+    let bodies: Vec<String> = vec![
+        format!(
+            "我的{region}轻量应用服务器带宽是{bw}Mbps，下载速度正常，\
+            但从服务器往外上传的速度只有{}Mbps左右。\
+            请帮忙检查一下出方向是否存在限速，如有请帮忙解除。",
+            speed_str
+        ),
+        format!(
+            "我购买的{region}轻量应用服务器是{bw}Mbps带宽，\
+            最近发现服务器上传速度只有{}Mbps，明显低于购买的带宽。\
+            麻烦帮忙看看是不是出网方向被限速了。",
+            speed_str
+        ),
+        format!(
+            "我有一台{region}轻量服务器，带宽{bw}Mbps，\
+            测试从服务器向外发送数据只有{}Mbps。\
+            请问是否有上行限速？能否帮忙恢复正常带宽？",
+            speed_str
+        ),
+    ];
+    let body = bodies.choose(&mut rng).unwrap();
+
+    format!(
+        "{}{}{}{}",
+        random_greeting(),
+        body,
+        samples_detail(samples),
+        random_ending()
+    )
+}
+
+/// 吞吐曲线显示被限在固定值时附在工单描述后的说明
+pub fn cap_note(cap_mbps: f64) -> String {
+    format!(
+        "测速过程中速度一直很平稳地卡在{:.1}Mbps左右，不像是网络波动，更像是被限速了。",
+        cap_mbps
+    )
+}
+
+/// 下载和上传同时限速时附在工单描述后的上传说明
+pub fn upload_note(upload_mbps: f64) -> String {
+    format!("另外上传速度也只有{:.1}Mbps，同样远低于购买的带宽。", upload_mbps)
+}
+
+/// 附在工单描述后的延迟说明
+pub fn latency_note(avg_ms: f64, p95_ms: f64, jitter_ms: f64, loss_ratio: f64) -> String {
+    format!(
+        "我同时测了网络延迟，平均{:.0}ms，P95 {:.0}ms，抖动{:.0}ms，连接失败率{:.0}%。",
+        avg_ms,
+        p95_ms,
+        jitter_ms,
+        loss_ratio * 100.0
+    )
+}
+
+/// 生成只有延迟 / 丢包异常（带宽未低于阈值）时的工单描述
+pub fn random_latency_description(
+    ctx: &TicketContext,
+    avg_ms: f64,
+    p95_ms: f64,
+    jitter_ms: f64,
+    loss_ratio: f64,
+) -> String {
+    let mut rng = rand::thread_rng();
+    let region = &ctx.region;
+    let bw = ctx.bandwidth_mbps;
+    let metrics = format!(
+        "平均延迟{:.0}ms，P95延迟{:.0}ms，抖动{:.0}ms，连接失败率{:.0}%",
+        avg_ms,
+        p95_ms,
+        jitter_ms,
+        loss_ratio * 100.0
+    );
+
+    let bodies: Vec<String> = vec![
+        format!(
+            "我的{region}轻量应用服务器最近网络很不稳定，\
+            测试下来{}，之前一直很正常。\
+            请帮忙检查一下网络或带宽是否有限制。",
+            metrics
+        ),
+        format!(
+            "我购买的{region}轻量应用服务器（{bw}Mbps）最近连接经常卡顿，\
+            实测{}。麻烦帮忙看看是不是被限速或者线路有问题。",
+            metrics
+        ),
+        format!(
+            "我有一台{region}轻量服务器，最近延迟和丢包明显变高，\
+            {}。请问服务器网络是否存在限制？能否帮忙处理一下？",
+            metrics
+        ),
+    ];
+    let body = bodies.choose(&mut rng).unwrap();
+
+    format!("{}{}{}", random_greeting(), body, random_ending())
+}
+
+fn random_greeting() -> &'static str {
+    let greetings: &[&str] = &["您好，", "你好，", "您好！\n", ""];
+    greetings.choose(&mut rand::thread_rng()).unwrap()
+}
+
+fn random_ending() -> &'static str {
     let endings: &[&str] = &[
         "谢谢！",
         "感谢！",
@@ -101,7 +270,107 @@ pub fn random_description(speed_mbps: f64) -> String {
         "谢谢，期待回复。",
         "",
     ];
+    endings.choose(&mut rand::thread_rng()).unwrap()
+}
+
+/// 多次采样时的说明，如 "我连续测了3次，分别是8.2、9.1、8.7Mbps。"
+fn samples_detail(samples: &[f64]) -> String {
+    if samples.len() > 1 {
+        let list: Vec<String> = samples.iter().map(|s| format!("{:.1}", s)).collect();
+        format!("我连续测了{}次，分别是{}Mbps。", samples.len(), list.join("、"))
+    } else {
+        String::new()
+    }
+}
+
+/// 多线程测速时附在工单描述后的分连接说明；单线程返回空字符串
+///
+/// 客服常问是单连接限速还是整机限速，附上每个连接的速度便于判断。
+pub fn streams_note(per_stream: &[f64]) -> String {
+    if per_stream.len() <= 1 {
+        return String::new();
+    }
+    let total: f64 = per_stream.iter().sum();
+    let list: Vec<String> = per_stream.iter().map(|s| format!("{:.1}", s)).collect();
+    format!(
+        "另外我用{}个连接同时下载测试，合计{:.1}Mbps，每个连接分别是{}Mbps。",
+        per_stream.len(),
+        total,
+        list.join("、")
+    )
+}
+
+/// 附在工单描述后的云监控带宽表（每小时一行），没有数据时返回空字符串
+///
+/// 客服常质疑自测数据，附上阿里云自己的监控数据作为旁证。
+pub fn metric_table(hours: u64, rows: &[crate::cms::HourlyRow]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let mut table = format!(
+        "\n\n附云监控最近{}小时的公网带宽（Mbps，平均/峰值）：\n时间 | 入方向 | 出方向",
+        hours
+    );
+    for (time, in_avg, in_max, out_avg, out_max) in rows {
+        table.push_str(&format!(
+            "\n{} | {:.1}/{:.1} | {:.1}/{:.1}",
+            time.format("%m-%d %H:00"),
+            in_avg,
+            in_max,
+            out_avg,
+            out_max
+        ));
+    }
+    table
+}
+
+/// 生成追加回复内容（限速未解除时回复在原工单上）
+///
+/// 引用原工单号以及提交时和当前的实测速度。
+pub fn random_followup(
+    ctx: &TicketContext,
+    ticket_id: &str,
+    previous_mbps: Option<f64>,
+    current_mbps: f64,
+) -> String {
+    let mut rng = rand::thread_rng();
+    let bw = ctx.bandwidth_mbps;
+
+    let current = format!("{:.1}", current_mbps);
+    let previous = match previous_mbps {
+        Some(p) => format!("提交工单时实测约{:.1}Mbps，", p),
+        None => String::new(),
+    };
+
+    let bodies: Vec<String> = vec![
+        format!(
+            "您好，关于工单{}：{}\
+            但我刚刚重新测试，下载速度仍然只有{}Mbps，限速似乎还没有解除。\
+            麻烦再帮忙检查一下。",
+            ticket_id, previous, current
+        ),
+        format!(
+            "你好，工单{}说已经处理了，{}\
+            不过现在测速还是{}Mbps左右，跟购买的{bw}Mbps差很多，\
+            问题应该还在，请再帮忙看看。",
+            ticket_id, previous, current
+        ),
+        format!(
+            "补充一下工单{}的情况：{}\
+            刚才又测了一次，下载速度{}Mbps，还是明显偏低。\
+            请帮忙确认限速是否真的解除了。",
+            ticket_id, previous, current
+        ),
+        format!(
+            "您好，工单{}的问题还没有解决。{}\
+            现在实测下载速度依然只有{}Mbps，麻烦再跟进一下。",
+            ticket_id, previous, current
+        ),
+    ];
+    let body = bodies.choose(&mut rng).unwrap();
+
+    let endings: &[&str] = &["谢谢！", "辛苦了，谢谢！", "感谢！", ""];
     let ending = *endings.choose(&mut rng).unwrap();
 
-    format!("{}{}{}", greeting, body, ending)
+    format!("{}{}", body, ending)
 }
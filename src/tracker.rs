@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::client::{self, WorkorderClient};
+use crate::config::Config;
+use crate::{history, notify};
+
+/// 已提交工单的状态跟踪器
+///
+/// 定期查询每个未完结的工单，状态变化时推送到飞书和 Telegram，
+/// 工单被阿里云处理完毕或关闭后停止跟踪。
+pub struct TicketTracker {
+    config: Config,
+    /// 工单号 -> 上次查询到的状态
+    tickets: Mutex<HashMap<String, String>>,
+}
+
+impl TicketTracker {
+    /// 创建跟踪器，并从历史记录恢复上次未完结的工单
+    pub fn new(config: Config) -> Self {
+        let restored: HashMap<String, String> =
+            history::open_tickets(&config.data_dir).into_iter().collect();
+        if !restored.is_empty() {
+            info!("从历史记录恢复 {} 个未完结工单", restored.len());
+        }
+        Self {
+            config,
+            tickets: Mutex::new(restored),
+        }
+    }
+
+    /// 开始跟踪一个新提交的工单
+    pub async fn track(&self, ticket_id: &str) {
+        let mut tickets = self.tickets.lock().await;
+        tickets
+            .entry(ticket_id.to_string())
+            .or_insert_with(|| "dealing".to_string());
+        info!("开始跟踪工单 {}（当前跟踪 {} 个）", ticket_id, tickets.len());
+    }
+
+    /// 停止跟踪一个工单（例如已被本工具关闭）
+    pub async fn untrack(&self, ticket_id: &str) {
+        self.tickets.lock().await.remove(ticket_id);
+    }
+
+    /// 当前正在跟踪的工单号
+    pub async fn tracked_ids(&self) -> Vec<String> {
+        self.tickets.lock().await.keys().cloned().collect()
+    }
+
+    /// 后台轮询循环，按 ticket_poll_interval 间隔查询工单状态
+    pub async fn run(&self) {
+        let interval = Duration::from_secs(self.config.ticket_poll_interval.max(10));
+        info!("工单状态跟踪已启动，轮询间隔 {}s", interval.as_secs());
+        loop {
+            tokio::time::sleep(interval).await;
+            self.poll_once().await;
+        }
+    }
+
+    /// 查询一轮所有在跟踪的工单
+    async fn poll_once(&self) {
+        let snapshot: Vec<(String, String)> = {
+            let tickets = self.tickets.lock().await;
+            tickets.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+        };
+        if snapshot.is_empty() {
+            return;
+        }
+
+        let client = WorkorderClient::new(self.config.clone());
+        for (ticket_id, old_status) in snapshot {
+            let ticket = match client.get_ticket(&ticket_id).await {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("查询工单 {} 状态失败: {:#}", ticket_id, e);
+                    continue;
+                }
+            };
+
+            let new_status = ticket.status().to_string();
+            if new_status == old_status {
+                continue;
+            }
+
+            info!(
+                "工单 {} 状态变化: {} -> {}",
+                ticket_id,
+                client::status_label(&old_status),
+                client::status_label(&new_status)
+            );
+            let msg = format!(
+                "📋 工单状态更新\n工单号: {}\n标题: {}\n状态: {} → {}",
+                ticket_id,
+                ticket.title.as_deref().unwrap_or("-"),
+                client::status_label(&old_status),
+                client::status_label(&new_status)
+            );
+            notify::broadcast(&self.config, &msg).await;
+            history::record(
+                &self.config.data_dir,
+                history::Event::TicketStatus {
+                    ticket_id: ticket_id.clone(),
+                    status: new_status.clone(),
+                },
+            );
+
+            let mut tickets = self.tickets.lock().await;
+            if client::is_finished(&new_status) {
+                tickets.remove(&ticket_id);
+                info!("工单 {} 已完结，停止跟踪", ticket_id);
+            } else {
+                tickets.insert(ticket_id, new_status);
+            }
+        }
+    }
+}